@@ -0,0 +1,108 @@
+//! Times the Intcode interpreter with and without its decoded instruction cache, against the
+//! original interpreter in `baseline`. Run with `cargo bench --bench intcode`.
+
+mod baseline;
+
+use aoc19::common::intcode::{IntcodeComputer, asm::assemble};
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// A tight arithmetic loop: the case the cache is for.
+const SUM_LOOP: &str = "
+.equ    N 1000000
+loop:   ADD  [i] #1 [i]
+        MUL  [i] #3 [t]
+        ADD  [sum] [t] [sum]
+        LT   [i] #N [flag]
+        JT   [flag] #loop
+        OUT  [sum]
+        HLT
+i:      .data 0
+t:      .data 0
+sum:    .data 0
+flag:   .data 0
+";
+
+/// Rewrites one of its own immediates every iteration, so the cache is invalidated constantly.
+const SELF_MODIFYING: &str = "
+.equ    N 100000000000
+loop:   ADD  [bump+2] #1 [bump+2]
+bump:   ADD  [i] #0 [i]
+        LT   [i] #N [flag]
+        JT   [flag] #loop
+        OUT  [i]
+        HLT
+i:      .data 0
+flag:   .data 0
+";
+
+/// Outputs a copy of itself; short enough that most of the time goes on fresh computers, like
+/// day 2's brute force.
+const QUINE: [i64; 16] = [
+    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+];
+
+fn run(memory: &[i64], decode_cache: bool) -> Vec<i64> {
+    let mut outputs = Vec::new();
+    IntcodeComputer::new(memory.to_vec())
+        .with_decode_cache(decode_cache)
+        .with_output(|o| outputs.push(o))
+        .run()
+        .unwrap();
+    outputs
+}
+
+fn run_baseline(memory: &[i64]) -> Vec<i64> {
+    let mut outputs = Vec::new();
+    baseline::IntcodeComputer::new_with_io(
+        memory.to_vec(),
+        || panic!("no input"),
+        |o| outputs.push(o),
+    )
+    .run();
+    outputs
+}
+
+fn time(runs: u32, mut f: impl FnMut() -> Vec<i64>) -> (Duration, Vec<i64>) {
+    let start = Instant::now();
+    let mut outputs = Vec::new();
+    for _ in 0..runs {
+        outputs = black_box(f());
+    }
+    (start.elapsed() / runs, outputs)
+}
+
+fn main() {
+    let benches = [
+        ("sum loop", assemble(SUM_LOOP).unwrap(), 5),
+        ("self-modifying", assemble(SELF_MODIFYING).unwrap(), 5),
+        ("quine", QUINE.to_vec(), 10_000),
+    ];
+
+    for (name, memory, runs) in benches {
+        let (base, expected) = time(runs, || run_baseline(black_box(&memory)));
+        let (uncached, outputs) = time(runs, || run(black_box(&memory), false));
+        assert_eq!(
+            outputs, expected,
+            "{}: uncached run gave different output",
+            name
+        );
+        let (cached, outputs) = time(runs, || run(black_box(&memory), true));
+        assert_eq!(
+            outputs, expected,
+            "{}: cached run gave different output",
+            name
+        );
+        println!(
+            "{:<16} baseline {:>12.2?}  uncached {:>12.2?}  cached {:>12.2?}  ({:.2}x baseline)",
+            name,
+            base,
+            uncached,
+            cached,
+            cached.as_secs_f64() / base.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Transpiled from a 47-word Intcode program.
+
+#![allow(dead_code)]
+
+use aoc19::common::intcode::{
+    IntcodeComputer, IntcodeError,
+    ports::{InputSource, OutputSink},
+    transpile::resume,
+};
+
+const PROGRAM: [i64; 47] = [
+    3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+    1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+    999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+];
+
+/// `[start, end)` runs of the cells holding translated instructions.
+const CODE: &[(usize, usize)] = &[
+    (0, 19),
+    (22, 45),
+    (46, 47),
+];
+
+fn load(mem: &[i64], addr: usize) -> i64 {
+    mem.get(addr).copied().unwrap_or(0)
+}
+
+fn store(mem: &mut Vec<i64>, addr: usize, value: i64) {
+    if addr >= mem.len() {
+        mem.resize(addr + 1, 0);
+    }
+    mem[addr] = value;
+}
+
+fn addr(a: i64) -> Option<usize> {
+    usize::try_from(a).ok()
+}
+
+fn is_code(addr: usize) -> bool {
+    let i = CODE.partition_point(|&(_, end)| end <= addr);
+    CODE.get(i).is_some_and(|&(start, _)| start <= addr)
+}
+
+fn intact(mem: &[i64]) -> bool {
+    CODE.iter()
+        .all(|&(start, end)| mem.get(start..end) == Some(&PROGRAM[start..end]))
+}
+
+#[allow(unused_mut, unreachable_code, clippy::all)]
+pub fn run<'a>(
+    mut mem: Vec<i64>,
+    mut input: impl InputSource + 'a,
+    mut output: impl OutputSink + 'a,
+) -> Result<IntcodeComputer<'a>, IntcodeError> {
+    let mut ip = 0usize;
+    let mut rb = 0i64;
+    if !intact(&mem) {
+        return resume(mem, ip, rb, input, output);
+    }
+    'run: loop {
+        match ip {
+            0 => {
+                //      0: IN   [21]
+                let Some(v0) = input.next_input() else { ip = 0; break 'run; };
+                store(&mut mem, 21, v0);
+                //      2: EQ   [21] #8 [20]
+                let v2 = (load(&mem, 21) == 8) as i64;
+                store(&mut mem, 20, v2);
+                //      6: JT   [20] #22
+                if load(&mem, 20) != 0 { ip = 22; continue 'run; }
+                ip = 9;
+            }
+            9 => {
+                //      9: LT   #8 [21] [20]
+                let v9 = (8 < load(&mem, 21)) as i64;
+                store(&mut mem, 20, v9);
+                //     13: JF   [20] #31
+                if load(&mem, 20) == 0 { ip = 31; continue 'run; }
+                ip = 16;
+            }
+            16 => {
+                //     16: JF   #0 #36
+                ip = 36; continue 'run;
+            }
+            22 => {
+                //     22: MUL  [21] #125 [20]
+                let Some(v22) = i64::checked_mul(load(&mem, 21), 125) else { ip = 22; break 'run; };
+                store(&mut mem, 20, v22);
+                //     26: OUT  [20]
+                if output.output(load(&mem, 20)).is_err() { ip = 26; break 'run; }
+                //     28: JT   #1 #46
+                ip = 46; continue 'run;
+            }
+            31 => {
+                //     31: OUT  #999
+                if output.output(999).is_err() { ip = 31; break 'run; }
+                //     33: JT   #1 #46
+                ip = 46; continue 'run;
+            }
+            36 => {
+                //     36: ADD  #1000 #1 [20]
+                let Some(v36) = i64::checked_add(1000, 1) else { ip = 36; break 'run; };
+                store(&mut mem, 20, v36);
+                //     40: OUT  [20]
+                if output.output(load(&mem, 20)).is_err() { ip = 40; break 'run; }
+                //     42: JT   #1 #46
+                ip = 46; continue 'run;
+            }
+            46 => {
+                //     46: HLT
+                ip = 46; break 'run;
+            }
+            _ => break 'run,
+        }
+    }
+    resume(mem, ip, rb, input, output)
+}
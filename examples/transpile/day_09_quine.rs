@@ -0,0 +1,83 @@
+//! Transpiled from a 16-word Intcode program.
+
+#![allow(dead_code)]
+
+use aoc19::common::intcode::{
+    IntcodeComputer, IntcodeError,
+    ports::{InputSource, OutputSink},
+    transpile::resume,
+};
+
+const PROGRAM: [i64; 16] = [
+    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+];
+
+/// `[start, end)` runs of the cells holding translated instructions.
+const CODE: &[(usize, usize)] = &[
+    (0, 16),
+];
+
+fn load(mem: &[i64], addr: usize) -> i64 {
+    mem.get(addr).copied().unwrap_or(0)
+}
+
+fn store(mem: &mut Vec<i64>, addr: usize, value: i64) {
+    if addr >= mem.len() {
+        mem.resize(addr + 1, 0);
+    }
+    mem[addr] = value;
+}
+
+fn addr(a: i64) -> Option<usize> {
+    usize::try_from(a).ok()
+}
+
+fn is_code(addr: usize) -> bool {
+    let i = CODE.partition_point(|&(_, end)| end <= addr);
+    CODE.get(i).is_some_and(|&(start, _)| start <= addr)
+}
+
+fn intact(mem: &[i64]) -> bool {
+    CODE.iter()
+        .all(|&(start, end)| mem.get(start..end) == Some(&PROGRAM[start..end]))
+}
+
+#[allow(unused_mut, unreachable_code, clippy::all)]
+pub fn run<'a>(
+    mut mem: Vec<i64>,
+    mut input: impl InputSource + 'a,
+    mut output: impl OutputSink + 'a,
+) -> Result<IntcodeComputer<'a>, IntcodeError> {
+    let mut ip = 0usize;
+    let mut rb = 0i64;
+    if !intact(&mem) {
+        return resume(mem, ip, rb, input, output);
+    }
+    'run: loop {
+        match ip {
+            0 => {
+                //      0: ARB  #1
+                let Some(r0) = rb.checked_add(1) else { ip = 0; break 'run; };
+                rb = r0;
+                //      2: OUT  rb-1
+                let Some(a2_0) = rb.checked_add(-1).and_then(addr) else { ip = 2; break 'run; };
+                if output.output(load(&mem, a2_0)).is_err() { ip = 2; break 'run; }
+                //      4: ADD  [100] #1 [100]
+                let Some(v4) = i64::checked_add(load(&mem, 100), 1) else { ip = 4; break 'run; };
+                store(&mut mem, 100, v4);
+                //      8: EQ   [100] #16 [101]
+                let v8 = (load(&mem, 100) == 16) as i64;
+                store(&mut mem, 101, v8);
+                //     12: JF   [101] #0
+                if load(&mem, 101) == 0 { ip = 0; continue 'run; }
+                ip = 15;
+            }
+            15 => {
+                //     15: HLT
+                ip = 15; break 'run;
+            }
+            _ => break 'run,
+        }
+    }
+    resume(mem, ip, rb, input, output)
+}
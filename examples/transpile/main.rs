@@ -0,0 +1,84 @@
+//! Checks transpiled day programs against the interpreter: both are run on the same inputs and
+//! must give the same outputs, final state and memory. Run with
+//! `cargo run --example transpile`.
+//!
+//! The programs are the examples from the day 5 and day 9 puzzles. The modules next to this file
+//! are `aoc19 intcode transpile` of them, and are checked to be up to date before anything runs.
+
+#[rustfmt::skip]
+mod day_05_compare;
+#[rustfmt::skip]
+mod day_09_quine;
+
+use aoc19::common::intcode::{IntcodeComputer, IntcodeError, State, memory::Memory, transpile};
+
+use std::collections::VecDeque;
+
+/// Outputs 999, 1000 or 1001 as its input is below, equal to or above 8.
+const DAY_05_COMPARE: &[i64] = &[
+    3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+    1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105,
+    1, 46, 98, 99,
+];
+
+/// Outputs a copy of itself.
+const DAY_09_QUINE: &[i64] = &[
+    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+];
+
+type Run =
+    fn(Vec<i64>, VecDeque<i64>, &mut dyn FnMut(i64)) -> Result<IntcodeComputer<'_>, IntcodeError>;
+
+/// How a run ended: its outputs, then its final state and memory if it didn't fail.
+type Outcome = (Vec<i64>, Result<(State, Memory), IntcodeError>);
+
+fn interpret(program: &[i64], input: &[i64]) -> Outcome {
+    let mut outputs = Vec::new();
+    let mut c = IntcodeComputer::new(program.to_vec())
+        .with_input(VecDeque::from(input.to_vec()))
+        .with_output(|o| outputs.push(o));
+    let result = c.run().map(|_| (c.get_state(), c.memory().clone()));
+    drop(c);
+    (outputs, result)
+}
+
+fn transpiled(run: Run, program: &[i64], input: &[i64]) -> Outcome {
+    let mut outputs = Vec::new();
+    let result = run(program.to_vec(), VecDeque::from(input.to_vec()), &mut |o| {
+        outputs.push(o)
+    })
+    .map(|c| (c.get_state(), c.memory().clone()));
+    (outputs, result)
+}
+
+fn check(name: &str, program: &[i64], source: &str, run: Run, inputs: &[Vec<i64>]) {
+    assert!(
+        transpile::transpile(program) == source,
+        "{}: out of date, regenerate it with `aoc19 intcode transpile`",
+        name
+    );
+
+    for input in inputs {
+        let expected = interpret(program, input);
+        let actual = transpiled(run, program, input);
+        assert_eq!(actual, expected, "{}: differs on input {:?}", name, input);
+        println!("{} {:?}: ok, output {:?}", name, input, actual.0);
+    }
+}
+
+fn main() {
+    check(
+        "day_05_compare",
+        DAY_05_COMPARE,
+        include_str!("day_05_compare.rs"),
+        |memory, input, output| day_05_compare::run(memory, input, output),
+        &(0..16).map(|i| vec![i]).collect::<Vec<_>>(),
+    );
+    check(
+        "day_09_quine",
+        DAY_09_QUINE,
+        include_str!("day_09_quine.rs"),
+        |memory, input, output| day_09_quine::run(memory, input, output),
+        &[vec![]],
+    );
+}
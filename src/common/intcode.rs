@@ -1,285 +1,1320 @@
-use std::iter::repeat_n;
-
-pub struct IntcodeComputer<FIF, POF>
-where
-    FIF: FnMut() -> i64,
-    POF: FnMut(i64),
-{
-    memory: Vec<i64>,
+pub mod ascii;
+pub mod asm;
+pub mod budget;
+pub mod cfg;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod history;
+pub mod memory;
+pub mod network;
+pub mod observer;
+pub mod peripheral;
+pub mod ports;
+pub mod profile;
+pub mod registry;
+pub mod replay;
+pub mod snapshot;
+pub mod symbolic;
+mod text;
+pub mod trace;
+pub mod transpile;
+
+use budget::{Budget, Limit};
+use history::{History, Undo};
+use memory::{Memory, MemoryBackend};
+use observer::{Access, Observer, WatchHit, Watchpoint};
+use ports::{InputPolicy, InputSource, OutputSink};
+use profile::Profile;
+use registry::{CustomOpcode, Exec, OpcodeRegistry, Role};
+use replay::{IoEvent, Recording};
+use snapshot::Snapshot;
+use trace::{TraceEvent, TraceSink};
+
+use std::{collections::VecDeque, error::Error, fmt, io, iter::repeat_n, mem, time::Instant};
+
+const MEMORY_WINDOW_RADIUS: usize = 4;
+const OPCODE_NUMBERS: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+const MAX_PARAMS: usize = 3;
+/// Instructions at or past this address aren't cached, so a jump far into sparse memory doesn't
+/// allocate a cache entry for every address below it.
+const DECODE_CACHE_LIMIT: usize = 1 << 20;
+/// Reading the clock every instruction would be slow, so deadlines are only checked this often.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// An Intcode machine. Without an input source or output sink attached, the machine stops with
+/// `State::BlockedOnInput` / `State::BlockedOnOutput` at I/O instructions and the caller completes
+/// them with `provide_input` / `get_output`; with them attached, I/O goes straight through.
+pub struct IntcodeComputer<'a> {
+    memory: Memory,
     instr: usize,
-    fetch_input: FIF,
-    provide_output: POF,
-    opcode: Opcode,
-    pmodes: Vec<ParameterMode>,
+    input_queue: VecDeque<i64>,
+    input: Option<Box<dyn InputSource + 'a>>,
+    input_policy: InputPolicy,
+    idle_reads: u64,
+    output_buffer: VecDeque<i64>,
+    output: Option<Box<dyn OutputSink + 'a>>,
+    opcodes: OpcodeRegistry,
+    op: Instruction,
+    decode_cache: bool,
+    decoded: Vec<Option<Instruction>>,
     state: State,
-    blocking_io: bool,
     relative_base: i64,
+    steps: u64,
+    budget: Budget,
+    exhausted: Option<Limit>,
+    profile: Option<Profile>,
+    observers: Vec<Box<dyn Observer + 'a>>,
+    watchpoints: Vec<(usize, Watchpoint<'a>)>,
+    next_watchpoint_id: usize,
+    watch_hit: Option<WatchHit>,
+    history: Option<History>,
+    recording: Option<Recording>,
+    trace: Option<Box<dyn TraceSink + 'a>>,
 }
 
-impl IntcodeComputer<fn() -> i64, fn(i64)> {
+impl<'a> IntcodeComputer<'a> {
     pub fn new(memory: Vec<i64>) -> Self {
         IntcodeComputer {
-            memory,
+            memory: Memory::from(memory),
             instr: 0,
-            fetch_input: || panic!("tried to fetch from uninitialized input"),
-            provide_output: |_| panic!("tried to write to uninitialized output"),
-            opcode: Opcode::Uninitialized,
-            pmodes: Vec::new(),
+            input_queue: VecDeque::new(),
+            input: None,
+            input_policy: InputPolicy::Block,
+            idle_reads: 0,
+            output_buffer: VecDeque::new(),
+            output: None,
+            opcodes: OpcodeRegistry::default(),
+            op: Instruction::UNINITIALIZED,
+            decode_cache: true,
+            decoded: Vec::new(),
             state: State::WaitingToRun,
-            blocking_io: true,
             relative_base: 0,
+            steps: 0,
+            budget: Budget::default(),
+            exhausted: None,
+            profile: None,
+            observers: Vec::new(),
+            watchpoints: Vec::new(),
+            next_watchpoint_id: 0,
+            watch_hit: None,
+            history: None,
+            recording: None,
+            trace: None,
         }
     }
-}
 
-impl<FIF, POF> IntcodeComputer<FIF, POF>
-where
-    FIF: FnMut() -> i64,
-    POF: FnMut(i64),
-{
-    pub fn new_with_io(memory: Vec<i64>, fetch_input: FIF, provide_output: POF) -> Self {
-        IntcodeComputer {
-            memory,
-            instr: 0,
-            fetch_input,
-            provide_output,
-            opcode: Opcode::Uninitialized,
-            pmodes: Vec::new(),
-            state: State::WaitingToRun,
-            blocking_io: false,
-            relative_base: 0,
-        }
+    pub fn with_input(mut self, input: impl InputSource + 'a) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Sets what happens when the input source has nothing to give. Defaults to
+    /// `InputPolicy::Block`.
+    pub fn with_input_policy(mut self, policy: InputPolicy) -> Self {
+        self.input_policy = policy;
+        self
+    }
+
+    pub fn with_output(mut self, output: impl OutputSink + 'a) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    pub fn with_trace(mut self, sink: impl TraceSink + 'a) -> Self {
+        self.set_trace(sink);
+        self
+    }
+
+    /// Moves memory into `backend`'s kind of storage. Defaults to `MemoryBackend::Dense`.
+    pub fn with_memory_backend(mut self, backend: MemoryBackend) -> Self {
+        self.memory = self.memory.into_backend(backend);
+        self
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Counts executions, operand reads and writes, and jumps per address; see `profile`.
+    pub fn with_profiler(mut self) -> Self {
+        self.profile = Some(Profile::default());
+        self
+    }
+
+    pub fn with_observer(mut self, observer: impl Observer + 'a) -> Self {
+        self.add_observer(observer);
+        self
+    }
+
+    pub fn with_watchpoint(mut self, watchpoint: Watchpoint<'a>) -> Self {
+        self.add_watchpoint(watchpoint);
+        self
+    }
+
+    /// Records enough of every instruction to undo it, so the computer can be stepped backwards;
+    /// see `History` for how `checkpoint_interval` and `max_checkpoints` bound what's kept.
+    pub fn with_history(mut self, checkpoint_interval: u64, max_checkpoints: usize) -> Self {
+        self.history = Some(History::new(checkpoint_interval, max_checkpoints));
+        self
+    }
+
+    /// Records every input and output from here on; see `recording`.
+    pub fn with_recording(mut self) -> Self {
+        self.recording = Some(Recording::default());
+        self
+    }
+
+    /// Turns the decoded instruction cache on or off (it's on by default). Without it, every
+    /// instruction is decoded afresh each time it runs.
+    pub fn with_decode_cache(mut self, enabled: bool) -> Self {
+        self.decode_cache = enabled;
+        self.decoded.clear();
+        self
+    }
+
+    /// Understands the opcodes in `opcodes` as well as the standard ones.
+    pub fn with_opcodes(mut self, opcodes: OpcodeRegistry) -> Self {
+        self.opcodes = opcodes;
+        self.decoded.clear();
+        self
+    }
+
+    /// A computer with no I/O attached, in the state captured by `snapshot`.
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut c = IntcodeComputer::new(Vec::new());
+        c.restore(snapshot);
+        c
+    }
+
+    /// Returns an independent copy of this computer in its current state, understanding the same
+    /// opcodes. I/O and the trace sink aren't carried over.
+    pub fn fork(&self) -> Self {
+        IntcodeComputer::from_snapshot(&self.snapshot()).with_opcodes(self.opcodes.clone())
     }
 
     pub fn set_day2_input(&mut self, noun: i64, verb: i64) {
-        self.memory[1] = noun;
-        self.memory[2] = verb;
+        self.write_mem(1, noun);
+        self.write_mem(2, verb);
     }
 
     pub fn get_day2_output(&self) -> i64 {
-        self.memory[0]
+        self.memory.read(0)
     }
 
-    pub fn run(&mut self, verbose: bool) {
+    pub fn run(&mut self) -> Result<(), IntcodeError> {
+        let hooked = self.hooked();
         loop {
-            self.read_op();
-            match self.opcode {
-                Opcode::Add | Opcode::Multiply => {
-                    let (p1, p2) = (self.get_src_param(1), self.get_src_param(2));
-                    let dst = self.get_dst_param(3);
-                    match self.opcode {
-                        Opcode::Add => {
-                            let result = p1 + p2;
-                            if verbose {
-                                println!("${} = {} + {} = {}", dst, p1, p2, result);
-                            }
-                            self.set_mem(dst, result);
-                        }
-                        Opcode::Multiply => {
-                            let result = p1 * p2;
-                            if verbose {
-                                println!("${} = {} * {} = {}", dst, p1, p2, result);
+            if !hooked && self.state != State::Terminated {
+                self.state = State::WaitingToRun;
+                self.run_unhooked();
+                if self.state == State::Terminated {
+                    return Ok(());
+                }
+            }
+            self.step()?;
+            if self.state != State::WaitingToRun {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether anything is attached that has to be told about, or checked against, every
+    /// instruction. If not, `run` can skip all of that bookkeeping with `run_unhooked`.
+    fn hooked(&self) -> bool {
+        self.trace.is_some()
+            || self.budget != Budget::default()
+            || self.profile.is_some()
+            || !self.observers.is_empty()
+            || !self.watchpoints.is_empty()
+            || self.history.is_some()
+            || self.recording.is_some()
+    }
+
+    /// `step` in a loop for a machine with nothing hooked into it. It stops, leaving the
+    /// instruction to `step`, at anything it doesn't handle itself: I/O, registered opcodes,
+    /// anything that would fail, and memory that isn't dense.
+    fn run_unhooked(&mut self) {
+        let Memory::Dense(memory) = &mut self.memory else {
+            return;
+        };
+        let decoded = &mut self.decoded;
+        let (mut instr, mut relative_base, mut steps) =
+            (self.instr, self.relative_base, self.steps);
+        loop {
+            let op = match decoded.get(instr) {
+                Some(Some(op)) => *op,
+                _ => match Instruction::decode_standard(memory, instr) {
+                    Some(op) => {
+                        if self.decode_cache && instr < DECODE_CACHE_LIMIT {
+                            if instr >= decoded.len() {
+                                decoded.resize(instr + 1, None);
                             }
-                            self.set_mem(dst, result);
+                            decoded[instr] = Some(op);
                         }
-                        _ => panic!("impossible"),
+                        op
+                    }
+                    None => break,
+                },
+            };
+            let param = |i| op.value(memory, relative_base, i);
+            match op.opcode {
+                Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                    let (Some(p1), Some(p2), Some(dst)) =
+                        (param(0), param(1), op.address(relative_base, 2))
+                    else {
+                        break;
+                    };
+                    let Some(result) = (match op.opcode {
+                        Opcode::Add => p1.checked_add(p2),
+                        Opcode::Multiply => p1.checked_mul(p2),
+                        Opcode::LessThan => Some((p1 < p2) as i64),
+                        _ => Some((p1 == p2) as i64),
+                    }) else {
+                        break;
+                    };
+                    if dst >= memory.len() {
+                        memory.extend(repeat_n(0, dst - memory.len() + 1));
                     }
-                    self.instr += 4;
+                    memory[dst] = result;
+                    forget_decoded(decoded, dst);
+                    instr += 4;
                 }
-                Opcode::Input => {
-                    if self.blocking_io {
-                        self.state = State::BlockedOnInput;
-                        return;
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let (Some(p), Some(dst)) = (param(0), param(1)) else {
+                        break;
+                    };
+                    if (p != 0) == (op.opcode == Opcode::JumpIfTrue) {
+                        let Ok(dst) = usize::try_from(dst) else {
+                            break;
+                        };
+                        instr = dst;
+                    } else {
+                        instr += 3;
                     }
+                }
+                Opcode::RelativeBaseOffset => {
+                    let Some(new) = param(0).and_then(|p| relative_base.checked_add(p)) else {
+                        break;
+                    };
+                    relative_base = new;
+                    instr += 2;
+                }
+                Opcode::Terminate => {
+                    self.op = op;
+                    self.state = State::Terminated;
+                    steps += 1;
+                    break;
+                }
+                _ => break,
+            }
+            steps += 1;
+        }
+        (self.instr, self.relative_base, self.steps) = (instr, relative_base, steps);
+    }
 
-                    let dst = self.get_dst_param(1);
-                    let input = (self.fetch_input)();
-                    self.set_mem(dst, input);
-                    if verbose {
-                        println!("${} = $input = {}", dst, self.get_mem(dst));
-                    }
-                    self.instr += 2;
+    /// Executes a single instruction. Blocking I/O instructions leave the instruction pointer
+    /// where it is and set the state to `BlockedOnInput` / `BlockedOnOutput` instead. Does
+    /// nothing once the program has terminated.
+    pub fn step(&mut self) -> Result<(), IntcodeError> {
+        if self.state == State::Terminated {
+            return Ok(());
+        }
+        self.state = State::WaitingToRun;
+        self.exhausted = None;
+        self.watch_hit = None;
+        if self.budget.max_steps.is_some_and(|max| self.steps >= max) {
+            self.exhaust(Limit::Steps);
+            return Ok(());
+        }
+        if self.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+            && self.budget.deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            self.exhaust(Limit::Deadline);
+            return Ok(());
+        }
+        if self
+            .history
+            .as_ref()
+            .is_some_and(|h| h.wants_checkpoint(self.steps))
+        {
+            let snapshot = self.snapshot();
+            self.history
+                .as_mut()
+                .expect("checked above")
+                .checkpoint(snapshot);
+        }
+        self.read_op()?;
+        let instr = self.instr;
+        let opcode = self.op.opcode;
+        self.observe(|o| o.fetch(instr, opcode));
+        match self.op.opcode {
+            Opcode::Add | Opcode::Multiply => {
+                let (p1, p2) = (self.get_src_param(1)?, self.get_src_param(2)?);
+                let dst = self.get_dst_param(3)?;
+                if !self.writable(dst) {
+                    return Ok(());
+                }
+                let result = match self.op.opcode {
+                    Opcode::Add => p1.checked_add(p2),
+                    Opcode::Multiply => p1.checked_mul(p2),
+                    _ => panic!("impossible"),
+                }
+                .ok_or_else(|| self.error(IntcodeErrorKind::Overflow))?;
+                self.store(dst, result);
+                self.trace(&[p1, p2], |e| e.write = Some((dst, result)))?;
+                self.instr += 4;
+            }
+            Opcode::Input => {
+                let dst = self.get_dst_param(1)?;
+                if !self.writable(dst) {
+                    return Ok(());
+                }
+                if self.input_queue.is_empty() && self.input.is_none() {
+                    self.state = State::BlockedOnInput;
+                    return Ok(());
                 }
-                Opcode::Output => {
-                    if self.blocking_io {
-                        self.state = State::BlockedOnOutput;
-                        return;
-                    }
 
-                    let p = self.get_src_param(1);
-                    if verbose {
-                        println!("$output = {}", p);
+                let next = match self.input_queue.pop_front() {
+                    Some(input) => Some(input),
+                    None => self.input.as_mut().expect("checked above").next_input(),
+                };
+                let input = match next {
+                    Some(input) => {
+                        self.idle_reads = 0;
+                        input
                     }
-                    (self.provide_output)(p);
-                    self.instr += 2;
-                }
-                Opcode::JumpIfTrue => {
-                    let (p, dst) = (self.get_src_param(1), self.get_src_param(2));
-                    if p != 0 {
-                        self.instr = unsafe_i64_to_usize(dst);
-                        if verbose {
-                            println!("$ip = {}", dst);
-                        }
-                    } else {
-                        self.instr += 3;
-                        if verbose {
-                            println!("no jump");
+                    None => {
+                        self.idle_reads += 1;
+                        match self.input_policy {
+                            InputPolicy::Block => {
+                                self.state = State::BlockedOnInput;
+                                return Ok(());
+                            }
+                            InputPolicy::Default(input) => input,
+                            InputPolicy::Fail => {
+                                return Err(self.error(IntcodeErrorKind::InputExhausted));
+                            }
                         }
                     }
+                };
+                self.store(dst, input);
+                if let Some(history) = &mut self.history {
+                    history.input(input);
                 }
-                Opcode::JumpIfFalse => {
-                    let (p, dst) = (self.get_src_param(1), self.get_src_param(2));
-                    if p == 0 {
-                        self.instr = unsafe_i64_to_usize(dst);
-                        if verbose {
-                            println!("$ip = {}", dst);
-                        }
-                    } else {
-                        self.instr += 3;
-                        if verbose {
-                            println!("no jump");
-                        }
-                    }
+                self.record(IoEvent::Input {
+                    step: self.steps,
+                    instr,
+                    value: input,
+                });
+                self.observe(|o| o.input(instr, input));
+                self.trace(&[], |e| {
+                    e.write = Some((dst, input));
+                    e.input = Some(input);
+                })?;
+                self.instr += 2;
+            }
+            Opcode::Output => {
+                if self.output.is_none() {
+                    self.state = State::BlockedOnOutput;
+                    return Ok(());
                 }
-                Opcode::LessThan => {
-                    let (p1, p2) = (self.get_src_param(1), self.get_src_param(2));
-                    let dst = self.get_dst_param(3);
-                    if p1 < p2 {
-                        self.set_mem(dst, 1);
-                        if verbose {
-                            println!("${} = 1", dst);
-                        }
-                    } else {
-                        self.set_mem(dst, 0);
-                        if verbose {
-                            println!("${} = 0", dst);
-                        }
-                    }
-                    self.instr += 4;
+
+                let p = self.get_src_param(1)?;
+                let result = self.output.as_mut().expect("checked above").output(p);
+                result.map_err(|e| self.error(IntcodeErrorKind::OutputFailed(e.kind())))?;
+                self.record(IoEvent::Output {
+                    step: self.steps,
+                    instr,
+                    value: p,
+                });
+                self.observe(|o| o.output(instr, p));
+                self.trace(&[p], |e| e.output = Some(p))?;
+                self.instr += 2;
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let (p, dst) = (self.get_src_param(1)?, self.get_src_param(2)?);
+                let jump = match self.op.opcode {
+                    Opcode::JumpIfTrue => p != 0,
+                    Opcode::JumpIfFalse => p == 0,
+                    _ => panic!("impossible"),
+                };
+                if let Some(profile) = &mut self.profile {
+                    profile.jump(self.instr, jump);
                 }
-                Opcode::Equals => {
-                    let (p1, p2) = (self.get_src_param(1), self.get_src_param(2));
-                    let dst = self.get_dst_param(3);
-                    if p1 == p2 {
-                        self.set_mem(dst, 1);
-                        if verbose {
-                            println!("${} = 1", dst);
-                        }
-                    } else {
-                        self.set_mem(dst, 0);
-                        if verbose {
-                            println!("${} = 0", dst);
-                        }
-                    }
-                    self.instr += 4;
+                if jump {
+                    let dst = self.to_address(dst)?;
+                    self.trace(&[p, dst as i64], |e| e.jump = Some(dst))?;
+                    self.instr = dst;
+                } else {
+                    self.trace(&[p, dst], |_| {})?;
+                    self.instr += 3;
                 }
-                Opcode::RelativeBaseOffset => {
-                    let param = self.get_src_param(1);
-                    if verbose {
-                        println!(
-                            "$relative_base += ({}) = {}",
-                            param,
-                            self.relative_base + param
-                        );
-                    }
-                    self.relative_base += param;
-                    self.instr += 2;
+            }
+            Opcode::LessThan | Opcode::Equals => {
+                let (p1, p2) = (self.get_src_param(1)?, self.get_src_param(2)?);
+                let dst = self.get_dst_param(3)?;
+                if !self.writable(dst) {
+                    return Ok(());
                 }
-                Opcode::Terminate => {
-                    self.state = State::Terminated;
-                    return;
+                let result = match self.op.opcode {
+                    Opcode::LessThan => p1 < p2,
+                    Opcode::Equals => p1 == p2,
+                    _ => panic!("impossible"),
+                } as i64;
+                self.store(dst, result);
+                self.trace(&[p1, p2], |e| e.write = Some((dst, result)))?;
+                self.instr += 4;
+            }
+            Opcode::RelativeBaseOffset => {
+                let param = self.get_src_param(1)?;
+                let old = self.relative_base;
+                let new = old
+                    .checked_add(param)
+                    .ok_or_else(|| self.error(IntcodeErrorKind::Overflow))?;
+                self.relative_base = new;
+                if let Some(history) = &mut self.history {
+                    history.relative_base(old);
                 }
-                Opcode::Uninitialized => panic!("opcode uninitialized (never ran self.read_op()?)"),
+                self.observe(|o| o.relative_base(instr, old, new));
+                self.trace(&[param], |e| e.relative_base = Some((old, new)))?;
+                self.instr += 2;
+            }
+            Opcode::Terminate => {
+                self.trace(&[], |_| {})?;
+                self.state = State::Terminated;
             }
+            Opcode::Custom(custom) => {
+                if !self.run_custom(custom)? {
+                    return Ok(());
+                }
+            }
+            Opcode::Uninitialized => panic!("opcode uninitialized (never ran self.read_op()?)"),
         }
+        self.complete(instr);
+        self.pause_on_watch_hit();
+        Ok(())
+    }
+
+    /// Runs a registered instruction, returning false if it was stopped by the budget before it
+    /// could start.
+    fn run_custom(&mut self, custom: CustomOpcode) -> Result<bool, IntcodeError> {
+        let execute = self
+            .opcodes
+            .execute(custom.number)
+            .expect("decoded from the registry");
+        let mut args = [0; MAX_PARAMS];
+        for (i, role) in custom.roles().iter().enumerate() {
+            args[i] = match role {
+                Role::Read => self.get_src_param(i + 1)?,
+                Role::Write => {
+                    let dst = self.get_dst_param(i + 1)?;
+                    if !self.writable(dst) {
+                        return Ok(false);
+                    }
+                    dst as i64
+                }
+            };
+        }
+
+        let mut exec = Exec {
+            computer: self,
+            args,
+            write: None,
+            relative_base: None,
+            jump: None,
+        };
+        execute(&mut exec)?;
+        let (write, relative_base, jump) = (exec.write, exec.relative_base, exec.jump);
+
+        let values: Vec<i64> = custom
+            .roles()
+            .iter()
+            .zip(args)
+            .filter(|(role, _)| **role == Role::Read)
+            .map(|(_, arg)| arg)
+            .collect();
+        self.trace(&values, |e| {
+            e.write = write;
+            e.relative_base = relative_base;
+            e.jump = jump;
+        })?;
+        self.instr = jump.unwrap_or(self.instr + custom.num_params() + 1);
+        Ok(true)
     }
 
     pub fn get_state(&self) -> State {
         self.state
     }
 
-    pub fn provide_input(&mut self, i: i64, verbose: bool) {
-        let dst = self.get_dst_param(1);
-        self.set_mem(dst, i);
-        if verbose {
-            println!("${} = $input = {}", dst, self.get_mem(dst));
+    /// How many input reads in a row found nothing available (and were blocked on or given the
+    /// default value). Reset whenever a real input value is read.
+    pub fn idle_reads(&self) -> u64 {
+        self.idle_reads
+    }
+
+    /// How many instructions have been executed, counting blocking I/O once it's completed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn budget(&self) -> Budget {
+        self.budget
+    }
+
+    /// Replaces the budget, e.g. with a bigger one so a machine in `State::BudgetExhausted` can
+    /// carry on.
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
+
+    /// Which limit stopped the machine, while it's in `State::BudgetExhausted`.
+    pub fn exhausted_limit(&self) -> Option<Limit> {
+        self.exhausted
+    }
+
+    pub fn add_observer(&mut self, observer: impl Observer + 'a) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Returns an id for `remove_watchpoint` and `WatchHit::id`.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint<'a>) -> usize {
+        let id = self.next_watchpoint_id;
+        self.next_watchpoint_id += 1;
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    /// Returns false if there's no watchpoint with that id.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|(i, _)| *i != id);
+        self.watchpoints.len() != len
+    }
+
+    /// The access that caused the last `State::WatchpointHit`. Cleared when the machine runs on.
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit
+    }
+
+    /// The counts gathered so far, if the computer was built `with_profiler`.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// The inputs and outputs recorded so far, if the computer was built `with_recording`.
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    /// Hands over what's been recorded so far, carrying on recording into a fresh `Recording`.
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recording.as_mut().map(mem::take)
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Undoes up to `n` instructions, returning how many were undone; fewer if the history
+    /// doesn't go back that far. Input read by undone instructions is queued to be read again,
+    /// but output can't be taken back.
+    pub fn step_back(&mut self, n: u64) -> Result<u64, IntcodeError> {
+        let mut undone = 0;
+        while undone < n && self.back_one()?.is_some() {
+            undone += 1;
+        }
+        Ok(undone)
+    }
+
+    /// Steps back until the instruction at `addr` is the next to run. If the history doesn't
+    /// go back to it, the computer is left as it was and this returns false.
+    pub fn run_back_to(&mut self, addr: usize) -> Result<bool, IntcodeError> {
+        self.run_back_until(|c, _| c.instr == addr)
+    }
+
+    /// Steps back to just before the last instruction that wrote to `addr`. If the history
+    /// doesn't go back to one, the computer is left as it was and this returns false.
+    pub fn run_back_to_write(&mut self, addr: usize) -> Result<bool, IntcodeError> {
+        self.run_back_until(|_, undo| undo.write.is_some_and(|(a, _)| a == addr))
+    }
+
+    pub fn get_instr(&self) -> usize {
+        self.instr
+    }
+
+    /// Moving the instruction pointer of a terminated program lets it run again.
+    pub fn set_instr(&mut self, instr: usize) {
+        self.instr = instr;
+        if self.state == State::Terminated {
+            self.state = State::WaitingToRun;
+        }
+    }
+
+    pub fn get_relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: i64) {
+        self.relative_base = relative_base;
+    }
+
+    pub fn opcodes(&self) -> &OpcodeRegistry {
+        &self.opcodes
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self
+                .memory
+                .segments()
+                .into_iter()
+                .map(|(start, values)| (start, values.to_vec()))
+                .collect(),
+            memory_backend: self.memory.backend(),
+            steps: self.steps,
+            instr: self.instr,
+            relative_base: self.relative_base,
+            opcode: self.op.opcode,
+            pmodes: self.op.modes[..self.op.opcode.num_params()].to_vec(),
+            state: self.state,
+            input_queue: self.input_queue.iter().copied().collect(),
+            output_buffer: self.output_buffer.iter().copied().collect(),
+        }
+    }
+
+    /// Puts this computer into the state captured by `snapshot`. The I/O closures and trace sink
+    /// are kept.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = Memory::from_segments(
+            snapshot.memory_backend,
+            snapshot
+                .memory
+                .iter()
+                .map(|(start, values)| (*start, &values[..])),
+        );
+        self.decoded.clear();
+        self.instr = snapshot.instr;
+        self.relative_base = snapshot.relative_base;
+        self.op = Instruction {
+            opcode: snapshot.opcode,
+            modes: [ParameterMode::Position; MAX_PARAMS],
+            params: [0; MAX_PARAMS],
+        };
+        for (i, mode) in snapshot.pmodes.iter().take(MAX_PARAMS).enumerate() {
+            self.op.modes[i] = *mode;
+        }
+        for i in 0..snapshot.opcode.num_params() {
+            self.op.params[i] = self.read_mem(self.instr + i + 1);
+        }
+        self.state = snapshot.state;
+        self.steps = snapshot.steps;
+        self.exhausted = None;
+        self.input_queue = snapshot.input_queue.iter().copied().collect();
+        self.output_buffer = snapshot.output_buffer.iter().copied().collect();
+    }
+
+    /// Sends an event for every executed instruction to `sink`, replacing any previous sink.
+    pub fn set_trace(&mut self, sink: impl TraceSink + 'a) {
+        self.trace = if sink.enabled() {
+            Some(Box::new(sink))
+        } else {
+            None
+        };
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+    }
+
+    pub fn provide_input(&mut self, i: i64) -> Result<(), IntcodeError> {
+        if self.state != State::BlockedOnInput {
+            return Err(self.error(IntcodeErrorKind::UnexpectedState(self.state)));
         }
+
+        let dst = self.get_dst_param(1)?;
+        self.store(dst, i);
+        if let Some(history) = &mut self.history {
+            history.input(i);
+        }
+        let instr = self.instr;
+        self.record(IoEvent::Input {
+            step: self.steps,
+            instr,
+            value: i,
+        });
+        self.observe(|o| o.input(instr, i));
+        self.idle_reads = 0;
+        self.trace(&[], |e| {
+            e.write = Some((dst, i));
+            e.input = Some(i);
+        })?;
+        self.complete(self.instr);
         self.instr += 2;
         self.state = State::WaitingToRun;
+        self.pause_on_watch_hit();
+        Ok(())
     }
 
-    pub fn get_output(&mut self, verbose: bool) -> i64 {
-        let p = self.get_src_param(1);
-        if verbose {
-            println!("$output = {}", p);
+    pub fn get_output(&mut self) -> Result<i64, IntcodeError> {
+        if self.state != State::BlockedOnOutput {
+            return Err(self.error(IntcodeErrorKind::UnexpectedState(self.state)));
         }
+
+        let p = self.get_src_param(1)?;
+        let instr = self.instr;
+        self.record(IoEvent::Output {
+            step: self.steps,
+            instr,
+            value: p,
+        });
+        self.observe(|o| o.output(instr, p));
+        self.trace(&[p], |e| e.output = Some(p))?;
+        self.complete(self.instr);
         self.instr += 2;
         self.state = State::WaitingToRun;
-        p
+        self.pause_on_watch_hit();
+        Ok(p)
     }
 
-    fn get_mem(&self, src: i64) -> i64 {
-        let src_usize = unsafe_i64_to_usize(src);
-        if src_usize >= self.memory.len() {
-            0
-        } else {
-            self.memory[src_usize]
+    /// Queues a value to be read by the next input instruction, ahead of any input source.
+    pub fn push_input(&mut self, i: i64) {
+        self.input_queue.push_back(i);
+    }
+
+    pub fn extend_input(&mut self, inputs: impl IntoIterator<Item = i64>) {
+        self.input_queue.extend(inputs);
+    }
+
+    /// Runs until the machine halts or needs input that isn't queued, collecting any output that
+    /// isn't going to an output sink. Returns the state it stopped in (`BlockedOnInput`,
+    /// `Terminated` or `BudgetExhausted`).
+    pub fn run_until_blocked(&mut self) -> Result<State, IntcodeError> {
+        loop {
+            self.run()?;
+            match self.state {
+                State::BlockedOnOutput => {
+                    let o = self.get_output()?;
+                    self.output_buffer.push_back(o);
+                }
+                state => return Ok(state),
+            }
+        }
+    }
+
+    pub fn drain_output(&mut self) -> Vec<i64> {
+        self.output_buffer.drain(..).collect()
+    }
+
+    /// Removes and returns the first `n` buffered outputs, or `None` (leaving the buffer alone)
+    /// if there are fewer than `n`.
+    pub fn take_outputs(&mut self, n: usize) -> Option<Vec<i64>> {
+        if self.output_buffer.len() < n {
+            return None;
+        }
+        Some(self.output_buffer.drain(..n).collect())
+    }
+
+    /// Emits a trace event for the current instruction, if tracing is enabled. `values` are the
+    /// resolved source operands and `effect` fills in whatever else the instruction did.
+    fn trace(
+        &mut self,
+        values: &[i64],
+        effect: impl FnOnce(&mut TraceEvent),
+    ) -> Result<(), IntcodeError> {
+        if self.trace.is_none() {
+            return Ok(());
+        }
+
+        let mut event = TraceEvent {
+            instr: self.instr,
+            opcode: self.op.opcode,
+            operands: (1..=self.op.opcode.num_params())
+                .map(|i| {
+                    let (mode, value) = self.get_pmode_and_immediate(i);
+                    disasm::Operand { mode, value }
+                })
+                .collect(),
+            values: values.to_vec(),
+            write: None,
+            relative_base: None,
+            input: None,
+            output: None,
+            jump: None,
+        };
+        effect(&mut event);
+
+        let result = self.trace.as_mut().expect("checked above").trace(&event);
+        result.map_err(|e| self.error(IntcodeErrorKind::TraceFailed(e.kind())))
+    }
+
+    /// Bookkeeping for an instruction at `instr` that has finished executing.
+    fn complete(&mut self, instr: usize) {
+        if let Some(history) = &mut self.history {
+            history.complete(self.steps, instr);
+        }
+        self.steps += 1;
+        if let Some(profile) = &mut self.profile {
+            profile.executed(instr, self.op.opcode);
+        }
+    }
+
+    fn run_back_until(
+        &mut self,
+        found: impl Fn(&Self, &Undo) -> bool,
+    ) -> Result<bool, IntcodeError> {
+        let start = self.steps;
+        while let Some(undo) = self.back_one()? {
+            if found(self, &undo) {
+                return Ok(true);
+            }
+        }
+        if self.steps < start {
+            self.replay(start)?;
+        }
+        Ok(false)
+    }
+
+    /// Undoes the last instruction, going back to a checkpoint and replaying if its undo record
+    /// has been dropped.
+    fn back_one(&mut self) -> Result<Option<Undo>, IntcodeError> {
+        let Some(history) = &mut self.history else {
+            return Ok(None);
+        };
+        if self.steps == 0 {
+            return Ok(None);
+        }
+        let undo = match history.pop(self.steps - 1) {
+            Some(undo) if undo.partial => {
+                let Some((checkpoint, inputs)) = history.rewind(undo.step) else {
+                    return Ok(None);
+                };
+                self.replay_from(&checkpoint, inputs, undo.step)?;
+                undo
+            }
+            Some(undo) => undo,
+            None => {
+                let target = self.steps;
+                let Some((checkpoint, inputs)) = history.rewind(target - 1) else {
+                    return Ok(None);
+                };
+                self.replay_from(&checkpoint, inputs, target)?;
+                let history = self.history.as_mut().expect("checked above");
+                match history.pop(target - 1) {
+                    Some(undo) => undo,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        if let Some((addr, old)) = undo.write {
+            self.write_mem(addr, old);
+        }
+        if let Some(relative_base) = undo.relative_base {
+            self.relative_base = relative_base;
+        }
+        if let Some(input) = undo.input {
+            self.input_queue.push_front(input);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.forget_from(undo.step);
+        }
+        self.instr = undo.instr;
+        self.steps = undo.step;
+        self.state = State::WaitingToRun;
+        self.exhausted = None;
+        self.watch_hit = None;
+        Ok(Some(undo))
+    }
+
+    /// Goes back to `checkpoint` and replays to step `target`, reading `inputs` before anything
+    /// already queued.
+    fn replay_from(
+        &mut self,
+        checkpoint: &Snapshot,
+        inputs: Vec<i64>,
+        target: u64,
+    ) -> Result<(), IntcodeError> {
+        let queue = mem::take(&mut self.input_queue);
+        let buffered = mem::take(&mut self.output_buffer);
+        self.restore(checkpoint);
+        self.input_queue = inputs.into_iter().chain(queue).collect();
+        self.output_buffer = buffered;
+        self.replay(target)
+    }
+
+    /// Runs forward to step `target` again, reading input only from the queue and with output,
+    /// tracing, recording, observers, watchpoints, the profiler and the budget all out of the way.
+    fn replay(&mut self, target: u64) -> Result<(), IntcodeError> {
+        let input = self.input.take();
+        let output = self.output.replace(Box::new(|_: i64| {}));
+        let trace = self.trace.take();
+        let recording = self.recording.take();
+        let observers = mem::take(&mut self.observers);
+        let watchpoints = mem::take(&mut self.watchpoints);
+        let profile = self.profile.take();
+        let budget = mem::take(&mut self.budget);
+
+        let mut result = Ok(());
+        while self.steps < target && result.is_ok() && self.state == State::WaitingToRun {
+            result = self.step();
+        }
+
+        self.input = input;
+        self.output = output;
+        self.trace = trace;
+        self.recording = recording;
+        self.observers = observers;
+        self.watchpoints = watchpoints;
+        self.profile = profile;
+        self.budget = budget;
+        result
+    }
+
+    /// A write made by the program itself, as opposed to `write_mem` from outside.
+    fn store(&mut self, addr: usize, i: i64) {
+        if let Some(profile) = &mut self.profile {
+            profile.wrote(addr);
+        }
+        if let Some(history) = &mut self.history {
+            history.wrote(addr, self.memory.read(addr));
+        }
+        if !self.observers.is_empty() || !self.watchpoints.is_empty() {
+            let (instr, old) = (self.instr, self.read_mem(addr));
+            self.observe(|o| o.write(instr, addr, old, i));
+            self.watch(addr, Access::Write, Some(old), i);
+        }
+        self.write_mem(addr, i);
+    }
+
+    fn record(&mut self, event: IoEvent) {
+        if let Some(recording) = &mut self.recording {
+            recording.push(event);
+        }
+    }
+
+    fn observe(&mut self, f: impl Fn(&mut dyn Observer)) {
+        for observer in &mut self.observers {
+            f(observer.as_mut());
+        }
+    }
+
+    /// Records the first access in an instruction that triggers a watchpoint.
+    fn watch(&mut self, addr: usize, access: Access, old: Option<i64>, value: i64) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+        let hit = self
+            .watchpoints
+            .iter()
+            .find(|(_, w)| w.matches(addr, access, value));
+        if let Some((id, _)) = hit {
+            self.watch_hit = Some(WatchHit {
+                id: *id,
+                instr: self.instr,
+                addr,
+                access,
+                old,
+                value,
+            });
+        }
+    }
+
+    fn pause_on_watch_hit(&mut self) {
+        if self.watch_hit.is_some() {
+            self.state = State::WatchpointHit;
+        }
+    }
+
+    fn exhaust(&mut self, limit: Limit) {
+        self.state = State::BudgetExhausted;
+        self.exhausted = Some(limit);
+    }
+
+    /// Checks a write to `addr` against the memory limit, stopping the machine if it's over.
+    fn writable(&mut self, addr: usize) -> bool {
+        if self.budget.max_memory.is_some_and(|max| addr >= max) {
+            self.exhaust(Limit::Memory);
+            return false;
+        }
+        true
+    }
+
+    fn get_mem(&mut self, src: i64) -> Result<i64, IntcodeError> {
+        let addr = self.to_address(src)?;
+        if let Some(profile) = &mut self.profile {
+            profile.read(addr);
+        }
+        let value = self.read_mem(addr);
+        if !self.observers.is_empty() || !self.watchpoints.is_empty() {
+            let instr = self.instr;
+            self.observe(|o| o.read(instr, addr, value));
+            self.watch(addr, Access::Read, None, value);
         }
+        Ok(value)
     }
 
-    fn set_mem(&mut self, dst: i64, i: i64) {
-        let dst_usize = unsafe_i64_to_usize(dst);
-        if dst_usize >= self.memory.len() {
-            self.memory
-                .extend(repeat_n(0, dst_usize - self.memory.len() + 1));
+    /// Reads memory directly; addresses past the end of memory read as 0.
+    pub fn read_mem(&self, addr: usize) -> i64 {
+        self.memory.read(addr)
+    }
+
+    /// Writes memory directly. Any cached instruction the write lands in is thrown away, so
+    /// self-modifying code is decoded again before it next runs.
+    pub fn write_mem(&mut self, addr: usize, i: i64) {
+        self.memory.write(addr, i);
+        forget_decoded(&mut self.decoded, addr);
+    }
+
+    fn read_op(&mut self) -> Result<(), IntcodeError> {
+        if let Some(Some(op)) = self.decoded.get(self.instr) {
+            self.op = *op;
+            return Ok(());
         }
-        self.memory[dst_usize] = i;
+
+        self.op = self.decode()?;
+        if self.decode_cache && self.instr < DECODE_CACHE_LIMIT {
+            if self.instr >= self.decoded.len() {
+                self.decoded.resize(self.instr + 1, None);
+            }
+            self.decoded[self.instr] = Some(self.op);
+        }
+        Ok(())
     }
 
-    fn read_op(&mut self) {
-        let mut op = self.memory[self.instr];
-        self.opcode = Opcode::try_from(op % 100).unwrap();
+    fn decode(&self) -> Result<Instruction, IntcodeError> {
+        let mut op = self.read_mem(self.instr);
+        let opcode = self
+            .opcodes
+            .opcode(op % 100)
+            .ok_or_else(|| self.error(IntcodeErrorKind::InvalidOpcode(op % 100)))?;
         op /= 100;
 
-        self.pmodes = Vec::new();
+        let mut modes = [ParameterMode::Position; MAX_PARAMS];
+        let mut i = 0;
         while op > 0 {
-            self.pmodes.push(ParameterMode::try_from(op % 10).unwrap());
+            let pmode = ParameterMode::try_from(op % 10)
+                .map_err(|_| self.error(IntcodeErrorKind::InvalidParameterMode(op % 10)))?;
+            if i < MAX_PARAMS {
+                modes[i] = pmode;
+            }
+            i += 1;
             op /= 10;
         }
+
+        let mut params = [0; MAX_PARAMS];
+        for (i, param) in params.iter_mut().take(opcode.num_params()).enumerate() {
+            *param = self.read_mem(self.instr + i + 1);
+        }
+        Ok(Instruction {
+            opcode,
+            modes,
+            params,
+        })
     }
 
-    fn get_src_param(&self, i: i64) -> i64 {
+    fn get_src_param(&mut self, i: usize) -> Result<i64, IntcodeError> {
         let (pmode, immediate) = self.get_pmode_and_immediate(i);
         match pmode {
             ParameterMode::Position => self.get_mem(immediate),
-            ParameterMode::Immediate => immediate,
-            ParameterMode::Relative => self.get_mem(immediate + self.relative_base),
+            ParameterMode::Immediate => Ok(immediate),
+            ParameterMode::Relative => self.get_mem(self.relative_address(immediate)?),
         }
     }
 
-    fn get_dst_param(&self, i: i64) -> i64 {
+    fn get_dst_param(&self, i: usize) -> Result<usize, IntcodeError> {
         let (pmode, immediate) = self.get_pmode_and_immediate(i);
         match pmode {
-            ParameterMode::Position => immediate,
-            ParameterMode::Immediate => panic!("immediate mode for write param"),
-            ParameterMode::Relative => immediate + self.relative_base,
+            ParameterMode::Position => self.to_address(immediate),
+            ParameterMode::Immediate => Err(self.error(IntcodeErrorKind::ImmediateModeWrite(i))),
+            ParameterMode::Relative => self.to_address(self.relative_address(immediate)?),
         }
     }
 
-    fn get_pmode_and_immediate(&self, i: i64) -> (ParameterMode, i64) {
-        let iusize = unsafe_i64_to_usize(i);
-        let pmode = if self.pmodes.len() >= iusize {
-            self.pmodes[iusize - 1]
-        } else {
-            ParameterMode::Position
-        };
-        let immediate = self.memory[self.instr + iusize];
-        (pmode, immediate)
+    fn relative_address(&self, offset: i64) -> Result<i64, IntcodeError> {
+        offset
+            .checked_add(self.relative_base)
+            .ok_or_else(|| self.error(IntcodeErrorKind::Overflow))
+    }
+
+    fn get_pmode_and_immediate(&self, i: usize) -> (ParameterMode, i64) {
+        (self.op.modes[i - 1], self.op.params[i - 1])
+    }
+
+    fn to_address(&self, i: i64) -> Result<usize, IntcodeError> {
+        usize::try_from(i).map_err(|_| self.error(IntcodeErrorKind::NegativeAddress(i)))
+    }
+
+    fn error(&self, kind: IntcodeErrorKind) -> IntcodeError {
+        let window_start = self
+            .instr
+            .saturating_sub(MEMORY_WINDOW_RADIUS)
+            .min(self.memory.len());
+        let window_end = (self.instr + MEMORY_WINDOW_RADIUS + 1).min(self.memory.len());
+        IntcodeError {
+            kind,
+            instr: self.instr,
+            op: self.read_mem(self.instr),
+            relative_base: self.relative_base,
+            window_start,
+            memory_window: (window_start..window_end)
+                .map(|addr| self.memory.read(addr))
+                .collect(),
+        }
     }
 }
 
+/// An instruction decoded once, so running it again doesn't redo the `%` and `/` arithmetic or
+/// re-read its parameters. Modes past the opcode's parameter count are `Position` and unused.
 #[derive(Copy, Clone, Debug)]
-enum Opcode {
+struct Instruction {
+    opcode: Opcode,
+    modes: [ParameterMode; MAX_PARAMS],
+    params: [i64; MAX_PARAMS],
+}
+
+impl Instruction {
+    const UNINITIALIZED: Instruction = Instruction {
+        opcode: Opcode::Uninitialized,
+        modes: [ParameterMode::Position; MAX_PARAMS],
+        params: [0; MAX_PARAMS],
+    };
+
+    /// Decodes the instruction at `instr` for `run_unhooked`, or returns `None` if it isn't a
+    /// well-formed standard one.
+    fn decode_standard(memory: &[i64], instr: usize) -> Option<Instruction> {
+        let word = |addr: usize| memory.get(addr).copied().unwrap_or(0);
+        let mut op = word(instr);
+        let opcode = Opcode::try_from(op % 100).ok()?;
+        op /= 100;
+
+        let mut modes = [ParameterMode::Position; MAX_PARAMS];
+        let mut i = 0;
+        while op > 0 {
+            let pmode = ParameterMode::try_from(op % 10).ok()?;
+            if i < MAX_PARAMS {
+                modes[i] = pmode;
+            }
+            i += 1;
+            op /= 10;
+        }
+
+        let mut params = [0; MAX_PARAMS];
+        for (i, param) in params.iter_mut().take(opcode.num_params()).enumerate() {
+            *param = word(instr + i + 1);
+        }
+        Some(Instruction {
+            opcode,
+            modes,
+            params,
+        })
+    }
+
+    /// The value of the `i`th (0-based) parameter, or `None` if it reads a negative address.
+    #[inline]
+    fn value(&self, memory: &[i64], relative_base: i64, i: usize) -> Option<i64> {
+        let addr = match self.modes[i] {
+            ParameterMode::Immediate => return Some(self.params[i]),
+            ParameterMode::Position => self.params[i],
+            ParameterMode::Relative => self.params[i].checked_add(relative_base)?,
+        };
+        Some(
+            memory
+                .get(usize::try_from(addr).ok()?)
+                .copied()
+                .unwrap_or(0),
+        )
+    }
+
+    /// The address the `i`th (0-based) parameter writes to, or `None` if it can't be written.
+    #[inline]
+    fn address(&self, relative_base: i64, i: usize) -> Option<usize> {
+        let addr = match self.modes[i] {
+            ParameterMode::Immediate => return None,
+            ParameterMode::Position => self.params[i],
+            ParameterMode::Relative => self.params[i].checked_add(relative_base)?,
+        };
+        usize::try_from(addr).ok()
+    }
+}
+
+/// Throws away any cached instruction a write to `addr` lands in.
+#[inline]
+fn forget_decoded(decoded: &mut [Option<Instruction>], addr: usize) {
+    let end = (addr + 1).min(decoded.len());
+    let start = addr.saturating_sub(MAX_PARAMS).min(end);
+    decoded[start..end].fill(None);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntcodeError {
+    pub kind: IntcodeErrorKind,
+    pub instr: usize,
+    pub op: i64,
+    pub relative_base: i64,
+    pub window_start: usize,
+    pub memory_window: Vec<i64>,
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at $ip = {} (op = {}, $relative_base = {}, memory[{}..] = {:?})",
+            self.kind,
+            self.instr,
+            self.op,
+            self.relative_base,
+            self.window_start,
+            self.memory_window
+        )
+    }
+}
+
+impl Error for IntcodeError {}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IntcodeErrorKind {
+    InvalidOpcode(i64),
+    InvalidParameterMode(i64),
+    ImmediateModeWrite(usize),
+    NegativeAddress(i64),
+    InputExhausted,
+    OutputFailed(io::ErrorKind),
+    UnexpectedState(State),
+    TraceFailed(io::ErrorKind),
+    /// An addition, multiplication or relative address that doesn't fit in an `i64`.
+    Overflow,
+    /// Raised by a registered opcode.
+    Custom(&'static str),
+}
+
+impl fmt::Display for IntcodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntcodeErrorKind::InvalidOpcode(o) => write!(f, "invalid opcode {}", o),
+            IntcodeErrorKind::InvalidParameterMode(m) => write!(f, "invalid parameter mode {}", m),
+            IntcodeErrorKind::ImmediateModeWrite(i) => {
+                write!(f, "immediate mode for write param {}", i)
+            }
+            IntcodeErrorKind::NegativeAddress(a) => write!(f, "negative address {}", a),
+            IntcodeErrorKind::InputExhausted => write!(f, "input source exhausted"),
+            IntcodeErrorKind::OutputFailed(e) => write!(f, "failed to write output: {}", e),
+            IntcodeErrorKind::UnexpectedState(s) => write!(f, "unexpected state {:?}", s),
+            IntcodeErrorKind::TraceFailed(e) => write!(f, "failed to write trace: {}", e),
+            IntcodeErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            IntcodeErrorKind::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
     Add,
     Multiply,
     Input,
@@ -290,6 +1325,8 @@ enum Opcode {
     Equals,
     RelativeBaseOffset,
     Terminate,
+    /// Registered with an `OpcodeRegistry`.
+    Custom(CustomOpcode),
     Uninitialized,
 }
 
@@ -313,8 +1350,66 @@ impl TryFrom<i64> for Opcode {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-enum ParameterMode {
+impl Opcode {
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        OPCODE_NUMBERS
+            .iter()
+            .map(|n| Opcode::try_from(*n).expect("bad opcode table"))
+            .find(|o| o.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Multiply => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpIfTrue => "JT",
+            Opcode::JumpIfFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equals => "EQ",
+            Opcode::RelativeBaseOffset => "ARB",
+            Opcode::Terminate => "HLT",
+            Opcode::Custom(c) => c.mnemonic,
+            Opcode::Uninitialized => "???",
+        }
+    }
+
+    pub fn num_params(&self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::RelativeBaseOffset => 1,
+            Opcode::Terminate | Opcode::Uninitialized => 0,
+            Opcode::Custom(c) => c.num_params(),
+        }
+    }
+
+    /// The (1-based) index of the (first) parameter this opcode writes to, if any.
+    pub fn dst_param(&self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => Some(3),
+            Opcode::Input => Some(1),
+            Opcode::Custom(c) => c
+                .roles()
+                .iter()
+                .position(|r| *r == Role::Write)
+                .map(|i| i + 1),
+            _ => None,
+        }
+    }
+
+    /// Whether the (1-based) `i`th parameter is written to.
+    pub fn writes(&self, i: usize) -> bool {
+        match self {
+            Opcode::Custom(c) => c.roles().get(i - 1) == Some(&Role::Write),
+            _ => self.dst_param() == Some(i),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParameterMode {
     Position,
     Immediate,
     Relative,
@@ -333,14 +1428,25 @@ impl TryFrom<i64> for ParameterMode {
     }
 }
 
+impl From<ParameterMode> for i64 {
+    fn from(m: ParameterMode) -> i64 {
+        match m {
+            ParameterMode::Position => 0,
+            ParameterMode::Immediate => 1,
+            ParameterMode::Relative => 2,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum State {
     WaitingToRun,
     BlockedOnInput,
     BlockedOnOutput,
     Terminated,
-}
-
-fn unsafe_i64_to_usize(i: i64) -> usize {
-    usize::try_from(i).unwrap()
+    /// Stopped by a limit in the machine's `Budget`, before executing the instruction at `$ip`.
+    BudgetExhausted,
+    /// Paused after an instruction that set off a watchpoint; `watch_hit` says which. Running
+    /// again carries on from the next instruction.
+    WatchpointHit,
 }
@@ -0,0 +1,108 @@
+use super::{IntcodeComputer, IntcodeError, State};
+
+use std::{iter, mem};
+
+/// Talks to an `IntcodeComputer` in text: input lines are sent as character codes and output is
+/// decoded back into text. Any output outside the ASCII range is kept apart as a non-ASCII
+/// result, which is how these programs report their numeric answer.
+///
+/// The wrapped computer shouldn't have an output sink, or there'll be nothing to decode.
+pub struct AsciiComputer<'a> {
+    computer: IntcodeComputer<'a>,
+    text: String,
+    results: Vec<i64>,
+}
+
+impl<'a> AsciiComputer<'a> {
+    pub fn new(computer: IntcodeComputer<'a>) -> Self {
+        AsciiComputer {
+            computer,
+            text: String::new(),
+            results: Vec::new(),
+        }
+    }
+
+    pub fn computer(&self) -> &IntcodeComputer<'a> {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut IntcodeComputer<'a> {
+        &mut self.computer
+    }
+
+    pub fn into_inner(self) -> IntcodeComputer<'a> {
+        self.computer
+    }
+
+    /// Queues `line` followed by a newline as input.
+    pub fn submit(&mut self, line: &str) {
+        self.computer
+            .extend_input(line.bytes().chain(iter::once(b'\n')).map(i64::from));
+    }
+
+    /// Runs until the program halts or needs more input, decoding everything it prints.
+    pub fn run(&mut self) -> Result<State, IntcodeError> {
+        let state = self.computer.run_until_blocked()?;
+        self.decode_buffered();
+        Ok(state)
+    }
+
+    /// Runs until the program has printed `prompt`, then returns all the unread text up to and
+    /// including it. Returns `None`, keeping the text, if the program halts or needs more input
+    /// first.
+    pub fn read_until(&mut self, prompt: &str) -> Result<Option<String>, IntcodeError> {
+        self.decode_buffered();
+        if let Some(i) = self.text.find(prompt) {
+            return Ok(Some(self.split_text(i + prompt.len())));
+        }
+
+        loop {
+            self.computer.run()?;
+            if self.computer.get_state() != State::BlockedOnOutput {
+                return Ok(None);
+            }
+            let o = self.computer.get_output()?;
+            self.decode(o);
+            if self.text.ends_with(prompt) {
+                return Ok(Some(mem::take(&mut self.text)));
+            }
+        }
+    }
+
+    /// Removes and returns the first complete line of unread text, without its newline.
+    pub fn read_line(&mut self) -> Option<String> {
+        let i = self.text.find('\n')?;
+        let mut line = self.split_text(i + 1);
+        line.pop();
+        Some(line)
+    }
+
+    /// Removes and returns all unread text, including any unfinished last line.
+    pub fn take_text(&mut self) -> String {
+        mem::take(&mut self.text)
+    }
+
+    /// Removes and returns the non-ASCII results printed so far.
+    pub fn take_results(&mut self) -> Vec<i64> {
+        mem::take(&mut self.results)
+    }
+
+    fn decode_buffered(&mut self) {
+        for o in self.computer.drain_output() {
+            self.decode(o);
+        }
+    }
+
+    fn decode(&mut self, o: i64) {
+        match u8::try_from(o) {
+            Ok(b) if b.is_ascii() => self.text.push(char::from(b)),
+            _ => self.results.push(o),
+        }
+    }
+
+    /// Removes and returns the unread text before byte `at`.
+    fn split_text(&mut self, at: usize) -> String {
+        let rest = self.text.split_off(at);
+        mem::replace(&mut self.text, rest)
+    }
+}
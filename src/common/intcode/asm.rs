@@ -0,0 +1,398 @@
+//! A small assembly language for Intcode.
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! .equ    LIMIT 10
+//!         ARB  #stack
+//! loop:   ADD  [counter] #1 [counter]
+//!         OUT  [counter]
+//!         LT   [counter] #LIMIT rb+0
+//!         JT   rb+0 #loop
+//!         HLT
+//! counter: .data 0
+//! greeting: .data "hi\n", -1
+//! stack:  .data 0
+//! ```
+//!
+//! Operands are `#expr` (immediate), `[expr]` (position) or `rb+expr` / `rb-N` (relative), where
+//! `expr` is an integer, a label or `.equ` constant, or a symbol plus or minus an integer. A bare
+//! `rb` is `rb+0`. `DATA` is accepted as an alias for `.data`, so disassembler output (minus the
+//! address column) assembles back to the same program.
+
+use super::{OPCODE_NUMBERS, Opcode, ParameterMode};
+
+use std::{collections::HashMap, error::Error, fmt};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.msg)
+    }
+}
+
+impl Error for AsmError {}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut asm = Assembler {
+        words: Vec::new(),
+        symbols: HashMap::new(),
+    };
+    for (i, line) in source.lines().enumerate() {
+        asm.parse_line(&mut Cursor::new(line, i + 1))?;
+    }
+
+    asm.words
+        .iter()
+        .map(|w| match w {
+            Word::Value(v) => Ok(*v),
+            Word::Expr(e) => asm.resolve(e),
+        })
+        .collect()
+}
+
+enum Word {
+    Value(i64),
+    Expr(Expr),
+}
+
+enum Expr {
+    Num(i64),
+    Symbol {
+        name: String,
+        offset: i64,
+        line: usize,
+        col: usize,
+    },
+}
+
+struct Assembler {
+    words: Vec<Word>,
+    symbols: HashMap<String, (i64, usize, usize)>,
+}
+
+impl Assembler {
+    fn parse_line(&mut self, c: &mut Cursor) -> Result<(), AsmError> {
+        loop {
+            c.skip_ws();
+            if c.at_end() {
+                return Ok(());
+            }
+
+            let col = c.col();
+            let name = c.ident()?;
+            if c.eat(':') {
+                self.define(name, self.words.len() as i64, c.line, col)?;
+                continue;
+            }
+
+            match name.to_ascii_lowercase().as_str() {
+                ".data" | "data" => self.parse_data(c)?,
+                ".equ" => {
+                    c.skip_ws();
+                    let sym_col = c.col();
+                    let sym = c.ident()?;
+                    c.skip_ws();
+                    let value = c.number()?;
+                    self.define(sym, value, c.line, sym_col)?;
+                }
+                _ => self.parse_instruction(c, &name, col)?,
+            }
+
+            c.skip_ws();
+            if !c.at_end() {
+                return Err(c.error("unexpected trailing input"));
+            }
+            return Ok(());
+        }
+    }
+
+    fn parse_data(&mut self, c: &mut Cursor) -> Result<(), AsmError> {
+        loop {
+            c.skip_ws();
+            if c.peek() == Some('"') {
+                for ch in c.string()? {
+                    self.words.push(Word::Value(ch as i64));
+                }
+            } else {
+                let expr = c.expr()?;
+                self.words.push(Word::Expr(expr));
+            }
+
+            c.skip_ws();
+            if !c.eat(',') {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_instruction(
+        &mut self,
+        c: &mut Cursor,
+        mnemonic: &str,
+        col: usize,
+    ) -> Result<(), AsmError> {
+        let (opcode, number) = OPCODE_NUMBERS
+            .iter()
+            .map(|n| (Opcode::try_from(*n).expect("bad opcode table"), *n))
+            .find(|(o, _)| o.mnemonic().eq_ignore_ascii_case(mnemonic))
+            .ok_or_else(|| c.error_at(col, format!("unknown mnemonic '{}'", mnemonic)))?;
+
+        let op_index = self.words.len();
+        self.words.push(Word::Value(0));
+
+        let mut op = number;
+        let mut scale = 100;
+        for i in 1..=opcode.num_params() {
+            c.skip_ws();
+            if i > 1 {
+                c.eat(',');
+                c.skip_ws();
+            }
+            if c.at_end() {
+                return Err(c.error(format!(
+                    "{} takes {} operands, got {}",
+                    opcode.mnemonic(),
+                    opcode.num_params(),
+                    i - 1
+                )));
+            }
+
+            let operand_col = c.col();
+            let (mode, expr) = c.operand()?;
+            if mode == ParameterMode::Immediate && opcode.dst_param() == Some(i) {
+                return Err(c.error_at(
+                    operand_col,
+                    format!(
+                        "operand {} of {} is written to and can't be immediate",
+                        i,
+                        opcode.mnemonic()
+                    ),
+                ));
+            }
+
+            op += scale * i64::from(mode);
+            scale *= 10;
+            self.words.push(Word::Expr(expr));
+        }
+
+        self.words[op_index] = Word::Value(op);
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        name: String,
+        value: i64,
+        line: usize,
+        col: usize,
+    ) -> Result<(), AsmError> {
+        if let Some((_, prev_line, prev_col)) = self.symbols.get(&name) {
+            return Err(AsmError {
+                line,
+                col,
+                msg: format!("'{}' already defined at {}:{}", name, prev_line, prev_col),
+            });
+        }
+        self.symbols.insert(name, (value, line, col));
+        Ok(())
+    }
+
+    fn resolve(&self, e: &Expr) -> Result<i64, AsmError> {
+        match e {
+            Expr::Num(n) => Ok(*n),
+            Expr::Symbol {
+                name,
+                offset,
+                line,
+                col,
+            } => match self.symbols.get(name) {
+                Some((value, _, _)) => Ok(value + offset),
+                None => Err(AsmError {
+                    line: *line,
+                    col: *col,
+                    msg: format!("undefined symbol '{}'", name),
+                }),
+            },
+        }
+    }
+}
+
+struct Cursor {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Cursor {
+    fn new(s: &str, line: usize) -> Self {
+        Cursor {
+            chars: s.chars().collect(),
+            pos: 0,
+            line,
+        }
+    }
+
+    fn col(&self) -> usize {
+        self.pos + 1
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        matches!(self.peek(), None | Some(';'))
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, msg: impl Into<String>) -> AsmError {
+        self.error_at(self.col(), msg)
+    }
+
+    fn error_at(&self, col: usize, msg: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            col,
+            msg: msg.into(),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, AsmError> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '.')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos || self.chars[start].is_ascii_digit() {
+            self.pos = start;
+            return Err(self.error("expected identifier"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn number(&mut self) -> Result<i64, AsmError> {
+        let start = self.pos;
+        if self.peek() == Some('-') || self.peek() == Some('+') {
+            self.pos += 1;
+        }
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let s: String = self.chars[start..self.pos].iter().collect();
+        s.parse().map_err(|_| {
+            self.pos = start;
+            self.error("expected integer")
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmError> {
+        let col = self.col();
+        match self.peek() {
+            Some(ch) if ch.is_ascii_digit() || ch == '-' || ch == '+' => {
+                Ok(Expr::Num(self.number()?))
+            }
+            _ => {
+                let name = self.ident()?;
+                let offset = match self.peek() {
+                    Some('+') | Some('-') => self.number()?,
+                    _ => 0,
+                };
+                Ok(Expr::Symbol {
+                    name,
+                    offset,
+                    line: self.line,
+                    col,
+                })
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<(ParameterMode, Expr), AsmError> {
+        if self.eat('#') {
+            return Ok((ParameterMode::Immediate, self.expr()?));
+        }
+
+        if self.eat('[') {
+            self.skip_ws();
+            let expr = self.expr()?;
+            self.skip_ws();
+            if !self.eat(']') {
+                return Err(self.error("expected ']'"));
+            }
+            return Ok((ParameterMode::Position, expr));
+        }
+
+        let rest = &self.chars[self.pos..];
+        if rest.starts_with(&['r', 'b'])
+            && !rest
+                .get(2)
+                .is_some_and(|ch| ch.is_ascii_alphanumeric() || *ch == '_' || *ch == '.')
+        {
+            self.pos += 2;
+            return match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    Ok((ParameterMode::Relative, self.expr()?))
+                }
+                Some('-') => Ok((ParameterMode::Relative, Expr::Num(self.number()?))),
+                _ => Ok((ParameterMode::Relative, Expr::Num(0))),
+            };
+        }
+
+        Err(self.error("expected operand ('#imm', '[pos]' or 'rb+offset')"))
+    }
+
+    fn string(&mut self) -> Result<Vec<char>, AsmError> {
+        let start_col = self.col();
+        self.pos += 1;
+
+        let mut s = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error_at(start_col, "unterminated string")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    s.push(match self.peek() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        _ => return Err(self.error("unknown escape sequence")),
+                    });
+                    self.pos += 1;
+                }
+                Some(ch) => {
+                    s.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::{fmt, time::Instant};
+
+/// Limits on how far an `IntcodeComputer` may run. A machine that hits one stops with
+/// `State::BudgetExhausted` before executing the instruction that would go over, and carries on
+/// from there once it's given a bigger budget.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Budget {
+    /// Total instructions the machine may have executed.
+    pub max_steps: Option<u64>,
+    /// Writes are limited to addresses below this.
+    pub max_memory: Option<usize>,
+    pub deadline: Option<Instant>,
+}
+
+/// Which part of a `Budget` ran out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Limit {
+    Steps,
+    Memory,
+    Deadline,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "step limit"),
+            Limit::Memory => write!(f, "memory limit"),
+            Limit::Deadline => write!(f, "deadline"),
+        }
+    }
+}
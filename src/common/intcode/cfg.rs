@@ -0,0 +1,345 @@
+//! Static control-flow graph recovery.
+//!
+//! Exploration starts at address 0 and follows fallthroughs and jumps whose targets can be worked
+//! out without running the program: immediate operands, or position-mode operands reading cells
+//! that no reachable instruction writes. The same goes for jump conditions, so `JT #1 #46` is an
+//! unconditional jump. Writes through the relative base are assumed to stay in the stack and data
+//! area and aren't counted as modifying anything.
+//!
+//! An unconditional jump is taken to be a call if some instruction pushes the address just after
+//! it onto the stack (`ADD #ret #0 rb+N` or similar), in which case both the callee and the return
+//! address are explored.
+
+use super::{
+    Opcode, ParameterMode,
+    disasm::{self, Line, Operand},
+    memory::Memory,
+};
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+/// How control leaves a basic block.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Exit {
+    Halt,
+    /// Runs straight on into the block at this address.
+    Fallthrough(usize),
+    /// A jump that's always taken.
+    Jump(usize),
+    /// A jump to a function that comes back to `ret`.
+    Call {
+        target: usize,
+        ret: usize,
+    },
+    Branch {
+        taken: usize,
+        not_taken: usize,
+    },
+    /// A jump whose target couldn't be resolved, with the fallthrough address if the jump might
+    /// not be taken.
+    Indirect {
+        not_taken: Option<usize>,
+    },
+    /// Ran into a word that isn't a valid instruction.
+    Invalid,
+}
+
+impl Exit {
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Fallthrough(a) | Exit::Jump(a) => vec![a],
+            Exit::Call { target, ret } => vec![target, ret],
+            Exit::Branch { taken, not_taken } => vec![taken, not_taken],
+            Exit::Indirect { not_taken } => not_taken.into_iter().collect(),
+            Exit::Halt | Exit::Invalid => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    /// Empty if the block starts with an invalid instruction.
+    pub lines: Vec<Line>,
+    pub exit: Exit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cfg {
+    /// Reachable blocks by start address.
+    pub blocks: BTreeMap<usize, Block>,
+    /// Cells written with position-mode destinations by reachable instructions.
+    pub written: BTreeSet<usize>,
+    memory: Memory,
+}
+
+impl Cfg {
+    pub fn build(program: &[i64]) -> Cfg {
+        let memory = Memory::from(program.to_vec());
+        let returns = return_addresses(&memory);
+
+        // `written` only ever grows, and only by position-mode destinations of instructions in
+        // the program, of which there are finitely many, so this settles. Unresolving a jump can
+        // make code reachable or unreachable, so `written` may keep writes from code a later pass
+        // no longer reaches; that only leaves the graph more conservative.
+        let mut written = BTreeSet::new();
+        loop {
+            let blocks = explore(&memory, &written, &returns);
+            let writes = writes(&blocks);
+            if writes.is_subset(&written) {
+                return Cfg {
+                    blocks,
+                    written,
+                    memory,
+                };
+            }
+            written.extend(writes);
+        }
+    }
+
+    /// Graphviz DOT with one node per block, labelled with its disassembly. Unresolved jumps go
+    /// to a dashed `?` node.
+    pub fn write_dot<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "digraph cfg {{")?;
+        writeln!(w, "    node [shape=box, fontname=\"monospace\"];")?;
+        for block in self.blocks.values() {
+            let mut label: String = block
+                .lines
+                .iter()
+                .map(|l| format!("{}\\l", escape(&l.to_string())))
+                .collect();
+            let mut attrs = String::new();
+            match block.exit {
+                Exit::Invalid => {
+                    let addr = block
+                        .lines
+                        .last()
+                        .map_or(block.start, |l| l.addr() + l.size());
+                    let data = Line::Data {
+                        addr,
+                        value: self.memory.read(addr),
+                    };
+                    label.push_str(&format!("{}\\l", escape(&data.to_string())));
+                    attrs.push_str(", color=red, style=dashed");
+                }
+                Exit::Indirect { .. } => attrs.push_str(", color=red"),
+                Exit::Halt => attrs.push_str(", peripheries=2"),
+                _ => {}
+            }
+            writeln!(w, "    b{} [label=\"{}\"{}];", block.start, label, attrs)?;
+
+            match block.exit {
+                Exit::Fallthrough(a) | Exit::Jump(a) => {
+                    writeln!(w, "    b{} -> b{};", block.start, a)?
+                }
+                Exit::Call { target, ret } => {
+                    writeln!(w, "    b{} -> b{} [label=\"call\"];", block.start, target)?;
+                    writeln!(w, "    b{} -> b{} [style=dotted];", block.start, ret)?;
+                }
+                Exit::Branch { taken, not_taken } => {
+                    writeln!(w, "    b{} -> b{} [label=\"T\"];", block.start, taken)?;
+                    writeln!(w, "    b{} -> b{} [label=\"F\"];", block.start, not_taken)?;
+                }
+                Exit::Indirect { not_taken } => {
+                    writeln!(
+                        w,
+                        "    unresolved{} [label=\"?\", shape=circle, style=dashed];",
+                        block.start
+                    )?;
+                    writeln!(
+                        w,
+                        "    b{} -> unresolved{} [style=dashed];",
+                        block.start, block.start
+                    )?;
+                    if let Some(a) = not_taken {
+                        writeln!(w, "    b{} -> b{} [label=\"F\"];", block.start, a)?;
+                    }
+                }
+                Exit::Halt | Exit::Invalid => {}
+            }
+        }
+        writeln!(w, "}}")
+    }
+}
+
+/// A reachable instruction, with its exit if it ends a block.
+struct Decoded {
+    opcode: Opcode,
+    operands: Vec<Operand>,
+    exit: Option<Exit>,
+}
+
+fn explore(
+    memory: &Memory,
+    written: &BTreeSet<usize>,
+    returns: &BTreeSet<usize>,
+) -> BTreeMap<usize, Block> {
+    let mut instrs: BTreeMap<usize, Decoded> = BTreeMap::new();
+    let mut invalid = BTreeSet::new();
+    let mut leaders = BTreeSet::from([0]);
+    let mut work = vec![0];
+    while let Some(addr) = work.pop() {
+        if instrs.contains_key(&addr) || invalid.contains(&addr) {
+            continue;
+        }
+        let Some((opcode, operands)) = disasm::decode(memory, addr) else {
+            invalid.insert(addr);
+            continue;
+        };
+
+        let exit = exit(memory, written, returns, addr, opcode, &operands);
+        match exit {
+            Some(exit) => {
+                for s in exit.successors() {
+                    leaders.insert(s);
+                    work.push(s);
+                }
+            }
+            None => work.push(addr + operands.len() + 1),
+        }
+        instrs.insert(
+            addr,
+            Decoded {
+                opcode,
+                operands,
+                exit,
+            },
+        );
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in &leaders {
+        let mut lines = Vec::new();
+        let mut addr = start;
+        let exit = loop {
+            let Some(d) = instrs.get(&addr) else {
+                break Exit::Invalid;
+            };
+            lines.push(Line::Instruction {
+                addr,
+                opcode: d.opcode,
+                operands: d.operands.clone(),
+            });
+            if let Some(exit) = d.exit {
+                break exit;
+            }
+            addr += d.operands.len() + 1;
+            if leaders.contains(&addr) {
+                break Exit::Fallthrough(addr);
+            }
+        };
+        blocks.insert(start, Block { start, lines, exit });
+    }
+    blocks
+}
+
+/// The exit for a control-flow instruction, or `None` for anything that just falls through.
+fn exit(
+    memory: &Memory,
+    written: &BTreeSet<usize>,
+    returns: &BTreeSet<usize>,
+    addr: usize,
+    opcode: Opcode,
+    operands: &[Operand],
+) -> Option<Exit> {
+    let jump_if = match opcode {
+        Opcode::Terminate => return Some(Exit::Halt),
+        Opcode::JumpIfTrue => true,
+        Opcode::JumpIfFalse => false,
+        _ => return None,
+    };
+
+    let not_taken = addr + 3;
+    let target = constant(memory, written, operands[1]).and_then(|t| usize::try_from(t).ok());
+    Some(match constant(memory, written, operands[0]) {
+        Some(c) if (c != 0) != jump_if => Exit::Fallthrough(not_taken),
+        Some(_) => match target {
+            Some(target) if returns.contains(&not_taken) => Exit::Call {
+                target,
+                ret: not_taken,
+            },
+            Some(t) => Exit::Jump(t),
+            None => Exit::Indirect { not_taken: None },
+        },
+        None => match target {
+            Some(taken) => Exit::Branch { taken, not_taken },
+            None => Exit::Indirect {
+                not_taken: Some(not_taken),
+            },
+        },
+    })
+}
+
+/// The value an operand is known to have whenever it's read, if there is one.
+fn constant(memory: &Memory, written: &BTreeSet<usize>, operand: Operand) -> Option<i64> {
+    match operand.mode {
+        ParameterMode::Immediate => Some(operand.value),
+        ParameterMode::Position => {
+            let addr = usize::try_from(operand.value).ok()?;
+            if written.contains(&addr) {
+                None
+            } else {
+                Some(memory.read(addr))
+            }
+        }
+        ParameterMode::Relative => None,
+    }
+}
+
+/// Constants pushed onto the stack anywhere in the program that could be return addresses.
+fn return_addresses(memory: &Memory) -> BTreeSet<usize> {
+    let mut returns = BTreeSet::new();
+    for line in disasm::disassemble(memory) {
+        let Line::Instruction {
+            opcode, operands, ..
+        } = line
+        else {
+            continue;
+        };
+        let (a, b) = match operands.as_slice() {
+            [a, b, dst]
+                if dst.mode == ParameterMode::Relative
+                    && a.mode == ParameterMode::Immediate
+                    && b.mode == ParameterMode::Immediate =>
+            {
+                (a.value, b.value)
+            }
+            _ => continue,
+        };
+        let value = match opcode {
+            Opcode::Add => a.checked_add(b),
+            Opcode::Multiply => a.checked_mul(b),
+            _ => None,
+        };
+        if let Some(addr) = value.and_then(|v| usize::try_from(v).ok()) {
+            returns.insert(addr);
+        }
+    }
+    returns
+}
+
+fn writes(blocks: &BTreeMap<usize, Block>) -> BTreeSet<usize> {
+    let mut writes = BTreeSet::new();
+    for line in blocks.values().flat_map(|b| &b.lines) {
+        if let Line::Instruction {
+            opcode, operands, ..
+        } = line
+            && let Some(dst) = opcode.dst_param()
+        {
+            let operand = operands[dst - 1];
+            if operand.mode == ParameterMode::Position
+                && let Ok(addr) = usize::try_from(operand.value)
+            {
+                writes.insert(addr);
+            }
+        }
+    }
+    writes
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,521 @@
+use super::{
+    IntcodeComputer, IntcodeError, Opcode, State, disasm,
+    observer::{Access, WatchHit, Watchpoint},
+    registry::OpcodeRegistry,
+    trace::TextTrace,
+};
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, VecDeque},
+    io::{self, BufRead, Write},
+    mem,
+    rc::Rc,
+    str::FromStr,
+};
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute n instructions (default 1)
+  c, continue          run until a breakpoint, input is needed, or the program halts
+  b, break [addr]      set a breakpoint on an instruction address (no addr: list breakpoints)
+  bo, break-op <op>    break before any instruction with mnemonic <op> (e.g. OUT)
+  d, delete <addr|op>  remove a breakpoint
+  w, watch <addr> [n] [r|w|rw]
+                       stop after any access of the given kind (default w) to n cells
+                       starting at addr (default 1); no args: list watchpoints
+  uw, unwatch <id>     remove a watchpoint
+  rs, rstep [n]        undo n instructions (default 1)
+  rto <addr>           step back until the instruction at addr is next to run
+  lastw <addr>         step back to just before the last write to addr
+  i, input <v>...      queue input values, consumed whenever the program reads input
+  x, mem <addr> [n]    print n memory cells starting at addr (default 8)
+  set <addr> <v>       write v to memory at addr
+  ip [addr]            show or set the instruction pointer
+  rb [v]               show or set the relative base
+  l, list [addr] [n]   disassemble n instructions starting at addr (default: ip, 10)
+  info                 show ip, relative base and state
+  trace [on|off]       toggle printing every executed instruction
+  h, help              show this message
+  q, quit              exit the debugger";
+
+const PROMPT: &str = "(icdb) ";
+/// How often the history for stepping backwards is checkpointed, and how many checkpoints it
+/// keeps.
+const CHECKPOINT_INTERVAL: u64 = 10_000;
+const MAX_CHECKPOINTS: usize = 100;
+
+pub struct Debugger {
+    computer: IntcodeComputer<'static>,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: Vec<Opcode>,
+    watchpoints: Vec<(usize, String)>,
+    pending_input: VecDeque<i64>,
+    outputs: Vec<i64>,
+    tracing: bool,
+    /// Where the trace is written while it's on, until it's copied to the command's output.
+    trace_buf: Rc<RefCell<Vec<u8>>>,
+}
+
+/// A writer into a buffer the debugger also holds, so a trace attached to the computer can be
+/// passed on to whatever writer the current command was given.
+struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    Breakpoint(usize),
+    OpcodeBreakpoint(Opcode),
+    NeedsInput,
+    Terminated,
+    BudgetExhausted,
+    Watchpoint(WatchHit),
+    Error(IntcodeError),
+}
+
+enum Flow {
+    Continue,
+    Quit,
+}
+
+impl Debugger {
+    pub fn new(memory: Vec<i64>) -> Self {
+        Debugger {
+            computer: IntcodeComputer::new(memory)
+                .with_history(CHECKPOINT_INTERVAL, MAX_CHECKPOINTS),
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            pending_input: VecDeque::new(),
+            outputs: Vec::new(),
+            tracing: false,
+            trace_buf: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn computer(&self) -> &IntcodeComputer<'static> {
+        &self.computer
+    }
+
+    pub fn outputs(&self) -> &[i64] {
+        &self.outputs
+    }
+
+    /// Reads commands from `input` until it's exhausted or `quit` is entered. When `interactive`
+    /// is set a prompt is printed before each command; otherwise (for command files) each command
+    /// is echoed after the prompt so the transcript reads the same way.
+    pub fn repl<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        out: &mut W,
+        interactive: bool,
+    ) -> io::Result<()> {
+        if interactive {
+            write!(out, "{}", PROMPT)?;
+            out.flush()?;
+        }
+        for line in input.lines() {
+            let line = line?;
+            if !interactive {
+                writeln!(out, "{}{}", PROMPT, line)?;
+            }
+
+            if let Flow::Quit = self.command(&line, out)? {
+                break;
+            }
+
+            if interactive {
+                write!(out, "{}", PROMPT)?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn command<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<Flow> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((cmd, args)) = words.split_first() else {
+            return Ok(Flow::Continue);
+        };
+
+        match self.dispatch(cmd, args, out) {
+            Ok(flow) => Ok(flow),
+            Err(CommandError::Io(e)) => Err(e),
+            Err(CommandError::Usage(msg)) => {
+                writeln!(out, "error: {}", msg)?;
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    fn dispatch<W: Write>(
+        &mut self,
+        cmd: &str,
+        args: &[&str],
+        out: &mut W,
+    ) -> Result<Flow, CommandError> {
+        match cmd {
+            "s" | "step" => {
+                let n = opt_arg(args, 0, 1)?;
+                let stop = self.step(n, out)?;
+                self.report(stop, out)?;
+            }
+            "c" | "continue" => {
+                let stop = self.cont(out)?;
+                self.report(Some(stop), out)?;
+            }
+            "b" | "break" => match args.first() {
+                None => {
+                    for b in &self.breakpoints {
+                        writeln!(out, "breakpoint at {}", b)?;
+                    }
+                    for o in &self.opcode_breakpoints {
+                        writeln!(out, "breakpoint on {}", o.mnemonic())?;
+                    }
+                }
+                Some(_) => {
+                    self.breakpoints.insert(arg(args, 0, "address")?);
+                }
+            },
+            "bo" | "break-op" => {
+                let opcode = opcode_arg(args, self.computer.opcodes())?;
+                if !self.opcode_breakpoints.contains(&opcode) {
+                    self.opcode_breakpoints.push(opcode);
+                }
+            }
+            "d" | "delete" => match self
+                .computer
+                .opcodes()
+                .from_mnemonic(args.first().copied().unwrap_or(""))
+            {
+                Some(opcode) => self.opcode_breakpoints.retain(|o| *o != opcode),
+                None => {
+                    let addr = arg(args, 0, "address or mnemonic")?;
+                    if !self.breakpoints.remove(&addr) {
+                        return Err(CommandError::Usage(format!("no breakpoint at {}", addr)));
+                    }
+                }
+            },
+            "w" | "watch" => {
+                if args.is_empty() {
+                    for (id, desc) in &self.watchpoints {
+                        writeln!(out, "watchpoint {}: {}", id, desc)?;
+                    }
+                    return Ok(Flow::Continue);
+                }
+
+                let addr: usize = arg(args, 0, "address")?;
+                let n: usize = opt_arg(args, 1, 1)?;
+                let access = match args.get(2).copied() {
+                    None | Some("w") => Access::Write,
+                    Some("r") => Access::Read,
+                    Some("rw") => Access::ReadWrite,
+                    Some(a) => {
+                        return Err(CommandError::Usage(format!("expected r/w/rw, got {}", a)));
+                    }
+                };
+                let end = end_addr(addr, n)?;
+                let id = self
+                    .computer
+                    .add_watchpoint(Watchpoint::new(addr..end, access));
+                let desc = format!("{:?} {}..{}", access, addr, end);
+                writeln!(out, "watchpoint {}: {}", id, desc)?;
+                self.watchpoints.push((id, desc));
+            }
+            "uw" | "unwatch" => {
+                let id = arg(args, 0, "watchpoint id")?;
+                if !self.computer.remove_watchpoint(id) {
+                    return Err(CommandError::Usage(format!("no watchpoint {}", id)));
+                }
+                self.watchpoints.retain(|(i, _)| *i != id);
+            }
+            "rs" | "rstep" => {
+                let n = opt_arg(args, 0, 1)?;
+                match self.computer.step_back(n) {
+                    Ok(undone) => {
+                        if undone < n {
+                            writeln!(out, "history only went back {} instructions", undone)?;
+                        }
+                        self.show_position(out)?;
+                    }
+                    Err(e) => self.report(Some(Stop::Error(e)), out)?,
+                }
+            }
+            "rto" => {
+                let addr = arg(args, 0, "address")?;
+                match self.computer.run_back_to(addr) {
+                    Ok(true) => self.show_position(out)?,
+                    Ok(false) => writeln!(out, "{} isn't in the history", addr)?,
+                    Err(e) => self.report(Some(Stop::Error(e)), out)?,
+                }
+            }
+            "lastw" => {
+                let addr = arg(args, 0, "address")?;
+                match self.computer.run_back_to_write(addr) {
+                    Ok(true) => self.show_position(out)?,
+                    Ok(false) => writeln!(out, "no write to {} in the history", addr)?,
+                    Err(e) => self.report(Some(Stop::Error(e)), out)?,
+                }
+            }
+            "i" | "input" => {
+                if args.is_empty() {
+                    return Err(CommandError::Usage(
+                        "expected at least one value".to_string(),
+                    ));
+                }
+                for i in 0..args.len() {
+                    let v = arg(args, i, "value")?;
+                    self.pending_input.push_back(v);
+                }
+            }
+            "x" | "mem" => {
+                let addr: usize = arg(args, 0, "address")?;
+                let n: usize = opt_arg(args, 1, 8)?;
+                let end = end_addr(addr, n)?;
+                for start in (addr..end).step_by(8) {
+                    write!(out, "{:>6}:", start)?;
+                    for a in start..start.saturating_add(8).min(end) {
+                        write!(out, " {}", self.computer.read_mem(a))?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+            "set" => {
+                let addr = arg(args, 0, "address")?;
+                let v = arg(args, 1, "value")?;
+                self.computer.write_mem(addr, v);
+            }
+            "ip" => match args.first() {
+                None => writeln!(out, "ip = {}", self.computer.get_instr())?,
+                Some(_) => self.computer.set_instr(arg(args, 0, "address")?),
+            },
+            "rb" => match args.first() {
+                None => writeln!(out, "rb = {}", self.computer.get_relative_base())?,
+                Some(_) => self.computer.set_relative_base(arg(args, 0, "value")?),
+            },
+            "l" | "list" => {
+                let mut addr = opt_arg(args, 0, self.computer.get_instr())?;
+                let n: usize = opt_arg(args, 1, 10)?;
+                for _ in 0..n {
+                    let line =
+                        disasm::line_with(self.computer.memory(), addr, self.computer.opcodes());
+                    writeln!(out, "{}", line)?;
+                    addr += line.size();
+                }
+            }
+            "info" => {
+                writeln!(
+                    out,
+                    "ip = {}, rb = {}, state = {:?}, pending input = {:?}",
+                    self.computer.get_instr(),
+                    self.computer.get_relative_base(),
+                    self.computer.get_state(),
+                    self.pending_input,
+                )?;
+            }
+            "trace" => {
+                self.tracing = match args.first() {
+                    None => !self.tracing,
+                    Some(&"on") => true,
+                    Some(&"off") => false,
+                    Some(a) => {
+                        return Err(CommandError::Usage(format!("expected on/off, got {}", a)));
+                    }
+                };
+                if self.tracing {
+                    self.computer
+                        .set_trace(TextTrace(SharedBuf(Rc::clone(&self.trace_buf))));
+                } else {
+                    self.computer.clear_trace();
+                }
+                writeln!(out, "trace {}", if self.tracing { "on" } else { "off" })?;
+            }
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            "q" | "quit" => return Ok(Flow::Quit),
+            _ => return Err(CommandError::Usage(format!("unknown command '{}'", cmd))),
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Executes up to `n` instructions, ignoring breakpoints.
+    pub fn step<W: Write>(&mut self, n: usize, out: &mut W) -> io::Result<Option<Stop>> {
+        for _ in 0..n {
+            if let Some(stop) = self.step_one(out)? {
+                return Ok(Some(stop));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Runs until a breakpoint is hit, input is needed, the program halts or an error occurs.
+    /// A breakpoint on the current instruction doesn't stop it from being executed.
+    pub fn cont<W: Write>(&mut self, out: &mut W) -> io::Result<Stop> {
+        if let Some(stop) = self.step_one(out)? {
+            return Ok(stop);
+        }
+        loop {
+            if let Some(stop) = self.check_breakpoints() {
+                return Ok(stop);
+            }
+            if let Some(stop) = self.step_one(out)? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    fn step_one<W: Write>(&mut self, out: &mut W) -> io::Result<Option<Stop>> {
+        if self.computer.get_state() != State::BlockedOnInput {
+            let result = self.computer.step();
+            self.flush_trace(out)?;
+            if let Err(e) = result {
+                return Ok(Some(Stop::Error(e)));
+            }
+        }
+
+        match self.computer.get_state() {
+            State::WaitingToRun => Ok(None),
+            State::BlockedOnInput => match self.pending_input.pop_front() {
+                Some(i) => match self.computer.provide_input(i) {
+                    Ok(()) => {
+                        self.flush_trace(out)?;
+                        writeln!(out, "input: {}", i)?;
+                        Ok(self.computer.watch_hit().map(Stop::Watchpoint))
+                    }
+                    Err(e) => Ok(Some(Stop::Error(e))),
+                },
+                None => Ok(Some(Stop::NeedsInput)),
+            },
+            State::BlockedOnOutput => match self.computer.get_output() {
+                Ok(o) => {
+                    self.flush_trace(out)?;
+                    writeln!(out, "output: {}", o)?;
+                    self.outputs.push(o);
+                    Ok(self.computer.watch_hit().map(Stop::Watchpoint))
+                }
+                Err(e) => Ok(Some(Stop::Error(e))),
+            },
+            State::Terminated => Ok(Some(Stop::Terminated)),
+            State::BudgetExhausted => Ok(Some(Stop::BudgetExhausted)),
+            State::WatchpointHit => Ok(self.computer.watch_hit().map(Stop::Watchpoint)),
+        }
+    }
+
+    /// Copies anything traced since the last call to `out`.
+    fn flush_trace<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let traced = mem::take(&mut *self.trace_buf.borrow_mut());
+        out.write_all(&traced)
+    }
+
+    /// Prints where a backwards step ended up.
+    fn show_position<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "step {}: {}",
+            self.computer.steps(),
+            disasm::line_with(
+                self.computer.memory(),
+                self.computer.get_instr(),
+                self.computer.opcodes()
+            )
+        )
+    }
+
+    fn check_breakpoints(&self) -> Option<Stop> {
+        let ip = self.computer.get_instr();
+        if self.breakpoints.contains(&ip) {
+            return Some(Stop::Breakpoint(ip));
+        }
+
+        let opcode = self
+            .computer
+            .opcodes()
+            .opcode(self.computer.read_mem(ip) % 100)?;
+        if self.opcode_breakpoints.contains(&opcode) {
+            return Some(Stop::OpcodeBreakpoint(opcode));
+        }
+        None
+    }
+
+    fn report<W: Write>(&self, stop: Option<Stop>, out: &mut W) -> io::Result<()> {
+        match stop {
+            None => {}
+            Some(Stop::Breakpoint(addr)) => writeln!(out, "breakpoint at {}", addr)?,
+            Some(Stop::OpcodeBreakpoint(o)) => writeln!(out, "breakpoint on {}", o.mnemonic())?,
+            Some(Stop::NeedsInput) => writeln!(out, "waiting for input (use 'input <value>')")?,
+            Some(Stop::Terminated) => writeln!(out, "terminated")?,
+            Some(Stop::BudgetExhausted) => writeln!(out, "budget exhausted")?,
+            Some(Stop::Watchpoint(hit)) => match hit.old {
+                Some(old) => writeln!(
+                    out,
+                    "watchpoint {}: {} wrote {} to {} (was {})",
+                    hit.id, hit.instr, hit.value, hit.addr, old
+                )?,
+                None => writeln!(
+                    out,
+                    "watchpoint {}: {} read {} from {}",
+                    hit.id, hit.instr, hit.value, hit.addr
+                )?,
+            },
+            Some(Stop::Error(e)) => writeln!(out, "error: {}", e)?,
+        }
+        writeln!(
+            out,
+            "{}",
+            disasm::line_with(
+                self.computer.memory(),
+                self.computer.get_instr(),
+                self.computer.opcodes()
+            )
+        )
+    }
+}
+
+enum CommandError {
+    Io(io::Error),
+    Usage(String),
+}
+
+impl From<io::Error> for CommandError {
+    fn from(e: io::Error) -> Self {
+        CommandError::Io(e)
+    }
+}
+
+fn arg<T: FromStr>(args: &[&str], i: usize, name: &str) -> Result<T, CommandError> {
+    let a = args
+        .get(i)
+        .ok_or_else(|| CommandError::Usage(format!("missing {}", name)))?;
+    a.parse()
+        .map_err(|_| CommandError::Usage(format!("invalid {} '{}'", name, a)))
+}
+
+fn opt_arg<T: FromStr>(args: &[&str], i: usize, default: T) -> Result<T, CommandError> {
+    match args.get(i) {
+        None => Ok(default),
+        Some(_) => arg(args, i, "argument"),
+    }
+}
+
+fn opcode_arg(args: &[&str], opcodes: &OpcodeRegistry) -> Result<Opcode, CommandError> {
+    let a = args
+        .first()
+        .ok_or_else(|| CommandError::Usage("missing mnemonic".to_string()))?;
+    opcodes
+        .from_mnemonic(a)
+        .ok_or_else(|| CommandError::Usage(format!("unknown mnemonic '{}'", a)))
+}
+
+/// The end of the `n` cells starting at `addr`.
+fn end_addr(addr: usize, n: usize) -> Result<usize, CommandError> {
+    addr.checked_add(n)
+        .ok_or_else(|| CommandError::Usage(format!("{} cells from {} is out of range", n, addr)))
+}
@@ -0,0 +1,925 @@
+//! Structured pseudo-code from an Intcode program's control-flow graph.
+//!
+//! ```text
+//! fn main() {
+//!     [21] = input()
+//!     fn_31()
+//!     while [20] < 10 {
+//!         output(rb[2] * 3)
+//!         ...
+//! ```
+//!
+//! `[a]` is the memory cell at `a`. `rb[n]` is a stack slot, numbered from the relative base on
+//! entry to the function; `[rb+n]` is used where the relative base can't be tracked. Functions are
+//! found from calls recognised by the CFG and named `fn_<addr>`, with address 0 as `main`. Loops
+//! come out as `while` or `loop`, two-way branches as `if`/`else`, and anything that doesn't fit
+//! the structure as `goto`.
+//!
+//! Within a block, values written to memory are substituted into later reads, so a comparison
+//! feeding a jump shows up in the `if`. A write whose only reads were all substituted this way is
+//! left out.
+
+use super::{
+    Opcode, ParameterMode,
+    cfg::{Block, Cfg, Exit},
+    disasm::{Line, Operand},
+};
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+const INDENT: &str = "    ";
+/// The virtual node every exit from a function leads to, for post-dominators.
+const EXIT: usize = usize::MAX;
+
+pub fn decompile(program: &[i64]) -> String {
+    let cfg = Cfg::build(program);
+
+    let mut entries = BTreeSet::from([0]);
+    for block in cfg.blocks.values() {
+        if let Exit::Call { target, .. } = block.exit {
+            entries.insert(target);
+        }
+    }
+    let mut mem_reads = HashMap::new();
+    for line in cfg.blocks.values().flat_map(|b| &b.lines) {
+        for operand in sources(line) {
+            if operand.mode == ParameterMode::Position {
+                *mem_reads.entry(Loc::Mem(operand.value)).or_default() += 1;
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for &entry in &entries {
+        if !cfg.blocks.contains_key(&entry) {
+            continue;
+        }
+        let function = Function::new(&cfg, &entries, &mem_reads, entry);
+        out.push(function.emit());
+    }
+    out.join("\n")
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 {
+        "main".to_string()
+    } else {
+        format!("fn_{}", entry)
+    }
+}
+
+/// The source operands of an instruction.
+fn sources(line: &Line) -> Vec<Operand> {
+    match line {
+        Line::Instruction {
+            opcode, operands, ..
+        } => operands
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| opcode.dst_param() != Some(i + 1))
+            .map(|(_, o)| *o)
+            .collect(),
+        Line::Data { .. } => Vec::new(),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Loc {
+    Mem(i64),
+    Slot(i64),
+    /// Relative to a relative base that couldn't be tracked.
+    Rel(i64),
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loc::Mem(a) => write!(f, "[{}]", a),
+            Loc::Slot(n) => write!(f, "rb[{}]", n),
+            Loc::Rel(k) if *k < 0 => write!(f, "[rb{}]", k),
+            Loc::Rel(k) => write!(f, "[rb+{}]", k),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum BinOp {
+    Add,
+    Mul,
+    Lt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Lt | BinOp::Ge | BinOp::Eq | BinOp::Ne => 1,
+            BinOp::Add => 2,
+            BinOp::Mul => 3,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Mul => "*",
+            BinOp::Lt => "<",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+        }
+    }
+
+    fn negated(self) -> Option<BinOp> {
+        match self {
+            BinOp::Lt => Some(BinOp::Ge),
+            BinOp::Ge => Some(BinOp::Lt),
+            BinOp::Eq => Some(BinOp::Ne),
+            BinOp::Ne => Some(BinOp::Eq),
+            BinOp::Add | BinOp::Mul => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Const(i64),
+    Load(Loc),
+    Input,
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// `a op b`, folded where the result is known.
+    fn bin(op: BinOp, a: Expr, b: Expr) -> Expr {
+        use Expr::Const;
+        let folded = match (op, &a, &b) {
+            (BinOp::Add, Const(x), Const(y)) => x.checked_add(*y).map(Const),
+            (BinOp::Mul, Const(x), Const(y)) => x.checked_mul(*y).map(Const),
+            (BinOp::Lt, Const(x), Const(y)) => Some(Const((x < y) as i64)),
+            (BinOp::Eq, Const(x), Const(y)) => Some(Const((x == y) as i64)),
+            (BinOp::Add, Const(0), e) | (BinOp::Add, e, Const(0)) => Some(e.clone()),
+            (BinOp::Mul, Const(1), e) | (BinOp::Mul, e, Const(1)) => Some(e.clone()),
+            (BinOp::Mul, Const(0), e) | (BinOp::Mul, e, Const(0)) if !e.has_input() => {
+                Some(Const(0))
+            }
+            _ => None,
+        };
+        folded.unwrap_or_else(|| Expr::Bin(op, Box::new(a), Box::new(b)))
+    }
+
+    fn reads(&self, loc: Loc) -> bool {
+        match self {
+            Expr::Load(l) => *l == loc,
+            Expr::Bin(_, a, b) => a.reads(loc) || b.reads(loc),
+            Expr::Const(_) | Expr::Input => false,
+        }
+    }
+
+    fn has_input(&self) -> bool {
+        match self {
+            Expr::Input => true,
+            Expr::Bin(_, a, b) => a.has_input() || b.has_input(),
+            Expr::Const(_) | Expr::Load(_) => false,
+        }
+    }
+
+    fn is_comparison(&self) -> bool {
+        matches!(self, Expr::Bin(op, _, _) if op.negated().is_some())
+    }
+
+    /// The condition "this is non-zero".
+    fn truthy(self) -> Expr {
+        if self.is_comparison() {
+            self
+        } else {
+            Expr::bin(BinOp::Ne, self, Expr::Const(0))
+        }
+    }
+
+    /// The condition "this is zero".
+    fn falsy(self) -> Expr {
+        match self {
+            Expr::Bin(op, a, b) if op.negated().is_some() => {
+                Expr::Bin(op.negated().expect("checked above"), a, b)
+            }
+            e => Expr::bin(BinOp::Eq, e, Expr::Const(0)),
+        }
+    }
+
+    fn fmt_prec(&self, f: &mut fmt::Formatter<'_>, parent: u8) -> fmt::Result {
+        match self {
+            Expr::Const(c) => write!(f, "{}", c),
+            Expr::Load(loc) => write!(f, "{}", loc),
+            Expr::Input => write!(f, "input()"),
+            Expr::Bin(op, a, b) => {
+                let prec = op.precedence();
+                if prec <= parent {
+                    write!(f, "(")?;
+                }
+                a.fmt_prec(f, prec - 1)?;
+                match (op, b.as_ref()) {
+                    (BinOp::Add, Expr::Const(c)) if *c < 0 => write!(f, " - {}", -(*c as i128))?,
+                    _ => {
+                        write!(f, " {} ", op.symbol())?;
+                        b.fmt_prec(f, prec)?;
+                    }
+                }
+                if prec <= parent {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_prec(f, 0)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Assign {
+        loc: Loc,
+        expr: Expr,
+        /// Reads later in the block that had `expr` substituted in.
+        uses: usize,
+        dead: bool,
+    },
+    Output(Expr),
+    /// A relative base change by a non-constant amount.
+    AdjustRb(Expr),
+    Call(usize),
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Goto(usize),
+    /// `cond` holds when `taken` is.
+    Branch {
+        cond: Expr,
+        taken: usize,
+        not_taken: usize,
+    },
+    Return,
+    IndirectJump {
+        cond: Expr,
+        target: Expr,
+        not_taken: Option<usize>,
+    },
+    Halt,
+    Invalid(usize),
+}
+
+struct Translated {
+    stmts: Vec<Stmt>,
+    term: Term,
+}
+
+/// Turns one block into statements, forwarding values written earlier in the block.
+struct Translator<'r> {
+    delta: Option<i64>,
+    known: HashMap<Loc, (Expr, usize)>,
+    stmts: Vec<Stmt>,
+    reads: &'r mut HashMap<Loc, usize>,
+}
+
+impl Translator<'_> {
+    fn loc(&self, operand: Operand) -> Loc {
+        match (operand.mode, self.delta) {
+            (ParameterMode::Relative, Some(d)) => Loc::Slot(d + operand.value),
+            (ParameterMode::Relative, None) => Loc::Rel(operand.value),
+            _ => Loc::Mem(operand.value),
+        }
+    }
+
+    fn read(&mut self, operand: Operand) -> Expr {
+        if operand.mode == ParameterMode::Immediate {
+            return Expr::Const(operand.value);
+        }
+        let loc = self.loc(operand);
+        *self.reads.entry(loc).or_default() += 1;
+        match self.known.get(&loc) {
+            Some((expr, i)) => {
+                if let Stmt::Assign { uses, .. } = &mut self.stmts[*i] {
+                    *uses += 1;
+                }
+                expr.clone()
+            }
+            None => Expr::Load(loc),
+        }
+    }
+
+    fn write(&mut self, operand: Operand, expr: Expr) {
+        let loc = self.loc(operand);
+        self.known.retain(|l, (e, _)| *l != loc && !e.reads(loc));
+        if !expr.has_input() && !expr.reads(loc) {
+            self.known.insert(loc, (expr.clone(), self.stmts.len()));
+        }
+        self.stmts.push(Stmt::Assign {
+            loc,
+            expr,
+            uses: 0,
+            dead: false,
+        });
+    }
+
+    fn translate(mut self, block: &Block) -> Translated {
+        let mut term = None;
+        for (i, line) in block.lines.iter().enumerate() {
+            let Line::Instruction {
+                opcode, operands, ..
+            } = line
+            else {
+                continue;
+            };
+            let ops = operands.as_slice();
+            match opcode {
+                Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                    let op = match opcode {
+                        Opcode::Add => BinOp::Add,
+                        Opcode::Multiply => BinOp::Mul,
+                        Opcode::LessThan => BinOp::Lt,
+                        _ => BinOp::Eq,
+                    };
+                    let (a, b) = (self.read(ops[0]), self.read(ops[1]));
+                    self.write(ops[2], Expr::bin(op, a, b));
+                }
+                Opcode::Input => self.write(ops[0], Expr::Input),
+                Opcode::Output => {
+                    let e = self.read(ops[0]);
+                    self.stmts.push(Stmt::Output(e));
+                }
+                Opcode::RelativeBaseOffset => match (self.read(ops[0]), self.delta) {
+                    (Expr::Const(c), Some(d)) => self.delta = Some(d + c),
+                    (e, _) => {
+                        self.stmts.push(Stmt::AdjustRb(e));
+                        self.delta = None;
+                    }
+                },
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse if i + 1 == block.lines.len() => {
+                    term = Some(self.jump(block.exit, *opcode, ops));
+                }
+                Opcode::Terminate => term = Some(Term::Halt),
+                _ => {}
+            }
+        }
+
+        let term = term.unwrap_or_else(|| match block.exit {
+            Exit::Fallthrough(a) | Exit::Jump(a) => Term::Goto(a),
+            _ => Term::Invalid(
+                block
+                    .lines
+                    .last()
+                    .map_or(block.start, |l| l.addr() + l.size()),
+            ),
+        });
+        Translated {
+            stmts: self.stmts,
+            term,
+        }
+    }
+
+    fn jump(&mut self, exit: Exit, opcode: Opcode, ops: &[Operand]) -> Term {
+        let cond = self.read(ops[0]);
+        let cond = if opcode == Opcode::JumpIfTrue {
+            cond.truthy()
+        } else {
+            cond.falsy()
+        };
+        match exit {
+            Exit::Fallthrough(a) | Exit::Jump(a) => Term::Goto(a),
+            Exit::Call { target, ret } => {
+                // The pushed return address is bookkeeping, not something to show.
+                let ret_addr = Expr::Const(ret as i64);
+                let pushed = self.stmts.iter_mut().rev().find(|s| {
+                    matches!(s, Stmt::Assign { loc: Loc::Slot(_) | Loc::Rel(_), expr, .. } if *expr == ret_addr)
+                });
+                if let Some(Stmt::Assign { dead, .. }) = pushed {
+                    *dead = true;
+                }
+                self.stmts.push(Stmt::Call(target));
+                Term::Goto(ret)
+            }
+            Exit::Branch { taken, not_taken } => Term::Branch {
+                cond,
+                taken,
+                not_taken,
+            },
+            Exit::Indirect { not_taken } => {
+                let target = self.read(ops[1]);
+                if not_taken.is_none() && ops[1].mode == ParameterMode::Relative {
+                    Term::Return
+                } else {
+                    Term::IndirectJump {
+                        cond,
+                        target,
+                        not_taken,
+                    }
+                }
+            }
+            Exit::Halt | Exit::Invalid => Term::Halt,
+        }
+    }
+}
+
+struct Loop {
+    body: BTreeSet<usize>,
+    follow: Option<usize>,
+}
+
+struct Function<'c> {
+    entry: usize,
+    entries: &'c BTreeSet<usize>,
+    translated: HashMap<usize, Translated>,
+    ipdom: HashMap<usize, usize>,
+    loops: HashMap<usize, Loop>,
+}
+
+impl<'c> Function<'c> {
+    fn new(
+        cfg: &'c Cfg,
+        entries: &'c BTreeSet<usize>,
+        mem_reads: &HashMap<Loc, usize>,
+        entry: usize,
+    ) -> Self {
+        // Successors within the function; calls come back to their return address, and jumps
+        // into another function end this one.
+        let succs = |b: usize| -> Vec<usize> {
+            let exit = cfg.blocks[&b].exit;
+            let next = match exit {
+                Exit::Call { ret, .. } => vec![ret],
+                e => e.successors(),
+            };
+            next.into_iter()
+                .filter(|s| cfg.blocks.contains_key(s) && (*s == entry || !entries.contains(s)))
+                .collect()
+        };
+
+        // Find the blocks and the relative base offset on entry to each.
+        let mut deltas: BTreeMap<usize, Option<i64>> = BTreeMap::from([(entry, Some(0))]);
+        let mut work = vec![entry];
+        while let Some(b) = work.pop() {
+            let exit_delta = deltas[&b].and_then(|d| exit_delta(&cfg.blocks[&b], d));
+            for s in succs(b) {
+                match deltas.get(&s) {
+                    None => {
+                        deltas.insert(s, exit_delta);
+                        work.push(s);
+                    }
+                    Some(d) if d.is_some() && *d != exit_delta => {
+                        deltas.insert(s, None);
+                        work.push(s);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        let blocks: BTreeSet<usize> = deltas.keys().copied().collect();
+
+        let mut reads = HashMap::new();
+        let mut translated: HashMap<usize, Translated> = blocks
+            .iter()
+            .map(|&b| {
+                let t = Translator {
+                    delta: deltas[&b],
+                    known: HashMap::new(),
+                    stmts: Vec::new(),
+                    reads: &mut reads,
+                };
+                (b, t.translate(&cfg.blocks[&b]))
+            })
+            .collect();
+        for t in translated.values_mut() {
+            for stmt in &mut t.stmts {
+                if let Stmt::Assign {
+                    loc, uses, dead, ..
+                } = stmt
+                {
+                    let total = match loc {
+                        Loc::Mem(_) => mem_reads.get(loc).copied().unwrap_or(0),
+                        Loc::Slot(_) => reads.get(loc).copied().unwrap_or(0),
+                        Loc::Rel(_) => usize::MAX,
+                    };
+                    if *uses > 0 && *uses == total {
+                        *dead = true;
+                    }
+                }
+            }
+        }
+
+        let succ_map: HashMap<usize, Vec<usize>> = blocks.iter().map(|&b| (b, succs(b))).collect();
+        let mut preds: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (&b, ss) in &succ_map {
+            for &s in ss {
+                preds.entry(s).or_default().push(b);
+            }
+        }
+
+        let dom = dominators(&blocks, entry, &preds);
+        let ipdom = post_dominators(&blocks, &succ_map);
+
+        let mut loops: HashMap<usize, Loop> = HashMap::new();
+        for (&u, ss) in &succ_map {
+            for &h in ss {
+                if dom[&u].contains(&h) {
+                    let body = &mut loops
+                        .entry(h)
+                        .or_insert_with(|| Loop {
+                            body: BTreeSet::from([h]),
+                            follow: None,
+                        })
+                        .body;
+                    let mut work = vec![u];
+                    while let Some(n) = work.pop() {
+                        if body.insert(n) {
+                            work.extend(preds.get(&n).into_iter().flatten().copied());
+                        }
+                    }
+                }
+            }
+        }
+        for (h, lp) in loops.iter_mut() {
+            let exits: BTreeSet<usize> = lp
+                .body
+                .iter()
+                .flat_map(|b| &succ_map[b])
+                .filter(|s| !lp.body.contains(s))
+                .copied()
+                .collect();
+            lp.follow = match ipdom.get(h) {
+                Some(p) if exits.contains(p) => Some(*p),
+                _ => exits.first().copied(),
+            };
+        }
+
+        Function {
+            entry,
+            entries,
+            translated,
+            ipdom,
+            loops,
+        }
+    }
+
+    fn emit(&self) -> String {
+        // The first pass finds which blocks are jumped to with `goto` and need labels.
+        let mut e = Emitter::new(self, BTreeSet::new());
+        e.function();
+        let labels = e.gotos;
+        let mut e = Emitter::new(self, labels);
+        e.function();
+        e.lines.join("\n") + "\n"
+    }
+}
+
+/// The relative base offset after running `block`, if it can be tracked.
+fn exit_delta(block: &Block, mut delta: i64) -> Option<i64> {
+    for line in &block.lines {
+        if let Line::Instruction {
+            opcode: Opcode::RelativeBaseOffset,
+            operands,
+            ..
+        } = line
+        {
+            if operands[0].mode != ParameterMode::Immediate {
+                return None;
+            }
+            delta += operands[0].value;
+        }
+    }
+    Some(delta)
+}
+
+fn dominators(
+    nodes: &BTreeSet<usize>,
+    entry: usize,
+    preds: &HashMap<usize, Vec<usize>>,
+) -> HashMap<usize, BTreeSet<usize>> {
+    let mut dom: HashMap<usize, BTreeSet<usize>> = nodes
+        .iter()
+        .map(|&n| {
+            let d = if n == entry {
+                BTreeSet::from([n])
+            } else {
+                nodes.clone()
+            };
+            (n, d)
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &n in nodes {
+            if n == entry {
+                continue;
+            }
+            let mut d = preds
+                .get(&n)
+                .into_iter()
+                .flatten()
+                .map(|p| dom[p].clone())
+                .reduce(|a, b| a.intersection(&b).copied().collect())
+                .unwrap_or_default();
+            d.insert(n);
+            if d != dom[&n] {
+                dom.insert(n, d);
+                changed = true;
+            }
+        }
+    }
+    dom
+}
+
+/// Immediate post-dominators, for the blocks that can reach an exit and have one before it.
+fn post_dominators(
+    nodes: &BTreeSet<usize>,
+    succs: &HashMap<usize, Vec<usize>>,
+) -> HashMap<usize, usize> {
+    // Post-dominators are dominators of the reversed graph, rooted at a virtual exit node that
+    // every block without successors leads to.
+    let mut rev_preds: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut rev_succs: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &n in nodes {
+        let ss = if succs[&n].is_empty() {
+            vec![EXIT]
+        } else {
+            succs[&n].clone()
+        };
+        for s in &ss {
+            rev_succs.entry(*s).or_default().push(n);
+        }
+        rev_preds.insert(n, ss);
+    }
+
+    let mut reaching = BTreeSet::from([EXIT]);
+    let mut work = vec![EXIT];
+    while let Some(n) = work.pop() {
+        for &p in rev_succs.get(&n).into_iter().flatten() {
+            if reaching.insert(p) {
+                work.push(p);
+            }
+        }
+    }
+    let rev_preds = rev_preds
+        .into_iter()
+        .filter(|(n, _)| reaching.contains(n))
+        .map(|(n, ps)| (n, ps.into_iter().filter(|p| reaching.contains(p)).collect()))
+        .collect();
+
+    let pdom = dominators(&reaching, EXIT, &rev_preds);
+    reaching
+        .iter()
+        .filter(|&&n| n != EXIT)
+        .filter_map(|n| {
+            let closest = pdom[n]
+                .iter()
+                .filter(|p| *p != n)
+                .max_by_key(|p| pdom[p].len())?;
+            (*closest != EXIT).then_some((*n, *closest))
+        })
+        .collect()
+}
+
+struct Emitter<'f, 'c> {
+    function: &'f Function<'c>,
+    labels: BTreeSet<usize>,
+    lines: Vec<String>,
+    depth: usize,
+    emitted: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+}
+
+impl<'f, 'c> Emitter<'f, 'c> {
+    fn new(function: &'f Function<'c>, labels: BTreeSet<usize>) -> Self {
+        Emitter {
+            function,
+            labels,
+            lines: Vec::new(),
+            depth: 0,
+            emitted: BTreeSet::new(),
+            gotos: BTreeSet::new(),
+        }
+    }
+
+    fn line(&mut self, s: String) {
+        self.lines
+            .push(format!("{}{}", INDENT.repeat(self.depth), s));
+    }
+
+    fn function(&mut self) {
+        self.line(format!("fn {}() {{", function_name(self.function.entry)));
+        self.depth += 1;
+        self.seq(self.function.entry, None, None, None);
+        self.depth -= 1;
+        self.line("}".to_string());
+    }
+
+    /// Emits code starting at block `b` until reaching `stop` or something that leaves the
+    /// current sequence. `lp` is the header of the innermost enclosing loop, and `entering` a loop
+    /// header whose body is being started (so it isn't treated as a `continue`).
+    fn seq(
+        &mut self,
+        mut b: usize,
+        stop: Option<usize>,
+        lp: Option<usize>,
+        mut entering: Option<usize>,
+    ) {
+        let f = self.function;
+        loop {
+            if Some(b) == stop {
+                return;
+            }
+            if entering != Some(b) {
+                if let Some(h) = lp {
+                    if b == h {
+                        self.line("continue".to_string());
+                        return;
+                    }
+                    if Some(b) == f.loops[&h].follow {
+                        self.line("break".to_string());
+                        return;
+                    }
+                }
+                if b != f.entry && f.entries.contains(&b) {
+                    self.line(format!("goto {}", function_name(b)));
+                    return;
+                }
+                if self.emitted.contains(&b) {
+                    self.gotos.insert(b);
+                    self.line(format!("goto L{}", b));
+                    return;
+                }
+                if f.loops.contains_key(&b) {
+                    self.emit_loop(b);
+                    match f.loops[&b].follow {
+                        Some(follow) => {
+                            b = follow;
+                            continue;
+                        }
+                        None => return,
+                    }
+                }
+            }
+            entering = None;
+
+            self.block_start(b);
+            let t = &f.translated[&b];
+            match &t.term {
+                Term::Goto(a) => b = *a,
+                Term::Branch {
+                    cond,
+                    taken,
+                    not_taken,
+                } => {
+                    // A join outside the enclosing loop can't be reached by falling out of the
+                    // `if`, since the loop has to be left first.
+                    let join = f
+                        .ipdom
+                        .get(&b)
+                        .copied()
+                        .filter(|j| lp.is_none_or(|h| f.loops[&h].body.contains(j)));
+                    match join {
+                        Some(j) => {
+                            let (cond, then, els) = if *taken == j {
+                                (cond.clone().falsy(), *not_taken, *taken)
+                            } else {
+                                (cond.clone(), *taken, *not_taken)
+                            };
+                            self.line(format!("if {} {{", cond));
+                            self.nested(then, Some(j), lp);
+                            if els != j {
+                                self.line("} else {".to_string());
+                                self.nested(els, Some(j), lp);
+                            }
+                            self.line("}".to_string());
+                            b = j;
+                        }
+                        None => {
+                            // Leaving the loop reads better inside the `if`.
+                            let (cond, then, els) = match lp.and_then(|h| f.loops[&h].follow) {
+                                Some(follow) if *not_taken == follow => {
+                                    (cond.clone().falsy(), *not_taken, *taken)
+                                }
+                                _ => (cond.clone(), *taken, *not_taken),
+                            };
+                            self.line(format!("if {} {{", cond));
+                            self.nested(then, None, lp);
+                            self.line("}".to_string());
+                            b = els;
+                        }
+                    }
+                }
+                Term::Return => {
+                    self.line("return".to_string());
+                    return;
+                }
+                Term::IndirectJump {
+                    cond,
+                    target,
+                    not_taken: Some(not_taken),
+                } => {
+                    self.line(format!("if {} {{", cond));
+                    self.depth += 1;
+                    self.line(format!("goto *{}", target));
+                    self.depth -= 1;
+                    self.line("}".to_string());
+                    b = *not_taken;
+                }
+                Term::IndirectJump { target, .. } => {
+                    self.line(format!("goto *{}", target));
+                    return;
+                }
+                Term::Halt => {
+                    self.line("halt()".to_string());
+                    return;
+                }
+                Term::Invalid(addr) => {
+                    self.line(format!("invalid instruction at {}", addr));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn nested(&mut self, b: usize, stop: Option<usize>, lp: Option<usize>) {
+        self.depth += 1;
+        self.seq(b, stop, lp, None);
+        self.depth -= 1;
+    }
+
+    /// Marks `b` as emitted and writes out its label and statements.
+    fn block_start(&mut self, b: usize) {
+        self.emitted.insert(b);
+        if self.labels.contains(&b) {
+            self.lines
+                .push(format!("{}L{}:", INDENT.repeat(self.depth), b));
+        }
+        for stmt in &self.function.translated[&b].stmts {
+            let s = match stmt {
+                Stmt::Assign {
+                    dead: false,
+                    loc,
+                    expr,
+                    ..
+                } => format!("{} = {}", loc, expr),
+                Stmt::Assign { .. } => continue,
+                Stmt::Output(e) => format!("output({})", e),
+                Stmt::AdjustRb(e) => format!("rb += {}", e),
+                Stmt::Call(target) => format!("{}()", function_name(*target)),
+            };
+            self.line(s);
+        }
+    }
+
+    fn emit_loop(&mut self, h: usize) {
+        let f = self.function;
+        let lp = &f.loops[&h];
+        let t = &f.translated[&h];
+        let visible = t
+            .stmts
+            .iter()
+            .any(|s| !matches!(s, Stmt::Assign { dead: true, .. }));
+
+        match &t.term {
+            Term::Branch {
+                cond,
+                taken,
+                not_taken,
+            } if !visible
+                && lp.follow.is_some_and(|f| f == *taken || f == *not_taken)
+                && (lp.body.contains(taken) || lp.body.contains(not_taken)) =>
+            {
+                let (cond, body) = if lp.body.contains(taken) {
+                    (cond.clone(), *taken)
+                } else {
+                    (cond.clone().falsy(), *not_taken)
+                };
+                self.block_start(h);
+                self.line(format!("while {} {{", cond));
+                self.nested(body, None, Some(h));
+            }
+            _ => {
+                self.line("loop {".to_string());
+                self.depth += 1;
+                self.seq(h, None, Some(h), Some(h));
+                self.depth -= 1;
+            }
+        }
+
+        let last = self.lines.last().map(|l| l.trim());
+        if last == Some("continue") {
+            self.lines.pop();
+        }
+        self.line("}".to_string());
+    }
+}
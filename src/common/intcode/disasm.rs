@@ -0,0 +1,161 @@
+use super::{Opcode, ParameterMode, memory::Memory, registry::OpcodeRegistry};
+
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Operand {
+    pub mode: ParameterMode,
+    pub value: i64,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "[{}]", self.value),
+            ParameterMode::Immediate => write!(f, "#{}", self.value),
+            ParameterMode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+            ParameterMode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    Instruction {
+        addr: usize,
+        opcode: Opcode,
+        operands: Vec<Operand>,
+    },
+    Data {
+        addr: usize,
+        value: i64,
+    },
+}
+
+impl Line {
+    pub fn addr(&self) -> usize {
+        match self {
+            Line::Instruction { addr, .. } | Line::Data { addr, .. } => *addr,
+        }
+    }
+
+    /// Number of memory words this line covers.
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Instruction { operands, .. } => operands.len() + 1,
+            Line::Data { .. } => 1,
+        }
+    }
+
+    /// The line without its address column, e.g. `ADD  [1] #4 rb+2`.
+    pub fn text(&self) -> String {
+        let line = self.to_string();
+        match line.split_once(": ") {
+            Some((_, text)) => text.to_string(),
+            None => line,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Instruction {
+                addr,
+                opcode,
+                operands,
+            } => {
+                write!(f, "{:>6}: {}", addr, opcode.mnemonic())?;
+                for (i, o) in operands.iter().enumerate() {
+                    let pad = if i == 0 {
+                        5usize.saturating_sub(opcode.mnemonic().len()).max(1)
+                    } else {
+                        1
+                    };
+                    write!(f, "{:pad$}{}", "", o)?;
+                }
+                Ok(())
+            }
+            Line::Data { addr, value } => write!(f, "{:>6}: DATA {}", addr, value),
+        }
+    }
+}
+
+/// Decodes the instruction at `addr`, or returns `None` if the word there isn't a well-formed
+/// instruction (unknown opcode or mode, immediate-mode destination, or operands running off the
+/// end of memory).
+pub fn decode(memory: &Memory, addr: usize) -> Option<(Opcode, Vec<Operand>)> {
+    decode_with(memory, addr, &OpcodeRegistry::default())
+}
+
+/// `decode`, also recognising the opcodes registered in `opcodes`.
+pub fn decode_with(
+    memory: &Memory,
+    addr: usize,
+    opcodes: &OpcodeRegistry,
+) -> Option<(Opcode, Vec<Operand>)> {
+    let word = memory.get(addr)?;
+    if word < 0 {
+        return None;
+    }
+
+    let opcode = opcodes.opcode(word % 100)?;
+    let mut modes = word / 100;
+
+    let mut operands = Vec::with_capacity(opcode.num_params());
+    for i in 1..=opcode.num_params() {
+        let mode = ParameterMode::try_from(modes % 10).ok()?;
+        modes /= 10;
+
+        if mode == ParameterMode::Immediate && opcode.writes(i) {
+            return None;
+        }
+        operands.push(Operand {
+            mode,
+            value: memory.get(addr + i)?,
+        });
+    }
+
+    if modes != 0 {
+        return None;
+    }
+
+    Some((opcode, operands))
+}
+
+/// Disassembles the single instruction (or data word) at `addr`.
+pub fn line_at(memory: &Memory, addr: usize) -> Line {
+    line_with(memory, addr, &OpcodeRegistry::default())
+}
+
+/// `line_at`, also recognising the opcodes registered in `opcodes`.
+pub fn line_with(memory: &Memory, addr: usize, opcodes: &OpcodeRegistry) -> Line {
+    match decode_with(memory, addr, opcodes) {
+        Some((opcode, operands)) => Line::Instruction {
+            addr,
+            opcode,
+            operands,
+        },
+        None => Line::Data {
+            addr,
+            value: memory.read(addr),
+        },
+    }
+}
+
+/// Linear-sweep disassembly of the whole of `memory`.
+pub fn disassemble(memory: &Memory) -> Vec<Line> {
+    disassemble_with(memory, &OpcodeRegistry::default())
+}
+
+/// `disassemble`, also recognising the opcodes registered in `opcodes`.
+pub fn disassemble_with(memory: &Memory, opcodes: &OpcodeRegistry) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < memory.len() {
+        let line = line_with(memory, addr, opcodes);
+        addr += line.size();
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,168 @@
+use super::snapshot::Snapshot;
+
+use std::{collections::VecDeque, mem};
+
+/// What it takes to undo one executed instruction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Undo {
+    /// The instruction's position in the run, as counted by `IntcodeComputer::steps`.
+    pub step: u64,
+    pub instr: usize,
+    /// The relative base before the instruction changed it.
+    pub relative_base: Option<i64>,
+    /// `(address, old value)` of the cell the instruction overwrote.
+    pub write: Option<(usize, i64)>,
+    pub input: Option<i64>,
+    /// The instruction (a registered opcode) wrote to more than one address, so undoing it means
+    /// replaying from a checkpoint instead.
+    pub partial: bool,
+}
+
+impl Undo {
+    const NONE: Undo = Undo {
+        step: 0,
+        instr: 0,
+        relative_base: None,
+        write: None,
+        input: None,
+        partial: false,
+    };
+}
+
+/// A record of a computer's recent execution that it can be wound back through, kept by
+/// `IntcodeComputer::with_history`.
+///
+/// Every `checkpoint_interval` steps the whole machine is snapshotted, and at most
+/// `max_checkpoints` of these are kept. Undo records are only kept back to the second-latest
+/// checkpoint; going back further restores an older checkpoint and replays forward from it, with
+/// the inputs it read the first time round. That keeps memory use to a bounded number of
+/// snapshots plus up to two intervals' worth of undo records.
+#[derive(Clone, Debug)]
+pub struct History {
+    checkpoint_interval: u64,
+    max_checkpoints: usize,
+    checkpoints: VecDeque<Snapshot>,
+    undo: VecDeque<Undo>,
+    /// `(step, value)` for every input read since the oldest checkpoint.
+    inputs: VecDeque<(u64, i64)>,
+    pending: Undo,
+}
+
+impl History {
+    pub(crate) fn new(checkpoint_interval: u64, max_checkpoints: usize) -> Self {
+        History {
+            checkpoint_interval: checkpoint_interval.max(1),
+            max_checkpoints: max_checkpoints.max(1),
+            checkpoints: VecDeque::new(),
+            undo: VecDeque::new(),
+            inputs: VecDeque::new(),
+            pending: Undo::NONE,
+        }
+    }
+
+    /// The earliest step the computer can be wound back to.
+    pub fn earliest_step(&self) -> Option<u64> {
+        let checkpoint = self.checkpoints.front().map(|c| c.steps);
+        let undo = self.undo.front().map(|u| u.step);
+        match (checkpoint, undo) {
+            (Some(c), Some(u)) => Some(c.min(u)),
+            (c, u) => c.or(u),
+        }
+    }
+
+    /// Undo records for the most recent instructions, oldest first.
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &Undo> {
+        self.undo.iter()
+    }
+
+    /// The most recent instruction still in the undo records that wrote to `addr`.
+    pub fn last_write(&self, addr: usize) -> Option<&Undo> {
+        self.undo
+            .iter()
+            .rev()
+            .find(|u| u.write.is_some_and(|(a, _)| a == addr))
+    }
+
+    pub(crate) fn wants_checkpoint(&self, steps: u64) -> bool {
+        steps.is_multiple_of(self.checkpoint_interval)
+            && self.checkpoints.back().is_none_or(|c| c.steps < steps)
+    }
+
+    pub(crate) fn checkpoint(&mut self, snapshot: Snapshot) {
+        if let Some(previous) = self.checkpoints.back() {
+            let keep_from = previous.steps;
+            while self.undo.front().is_some_and(|u| u.step < keep_from) {
+                self.undo.pop_front();
+            }
+        }
+        self.checkpoints.push_back(snapshot);
+        if self.checkpoints.len() > self.max_checkpoints {
+            self.checkpoints.pop_front();
+            let oldest = self.checkpoints.front().expect("just checked").steps;
+            while self.inputs.front().is_some_and(|(s, _)| *s < oldest) {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    pub(crate) fn wrote(&mut self, addr: usize, old: i64) {
+        match self.pending.write {
+            None => self.pending.write = Some((addr, old)),
+            Some((first, _)) if first == addr => {}
+            Some(_) => self.pending.partial = true,
+        }
+    }
+
+    pub(crate) fn relative_base(&mut self, old: i64) {
+        self.pending.relative_base.get_or_insert(old);
+    }
+
+    pub(crate) fn input(&mut self, value: i64) {
+        self.pending.input = Some(value);
+    }
+
+    /// Files the record for the instruction at `instr`, which was step number `step`.
+    pub(crate) fn complete(&mut self, step: u64, instr: usize) {
+        let undo = Undo {
+            step,
+            instr,
+            ..mem::replace(&mut self.pending, Undo::NONE)
+        };
+        if let Some(input) = undo.input {
+            self.inputs.push_back((step, input));
+        }
+        self.undo.push_back(undo);
+    }
+
+    /// Takes back the record for the last instruction, if it's still held.
+    pub(crate) fn pop(&mut self, step: u64) -> Option<Undo> {
+        if self.undo.back().is_none_or(|u| u.step != step) {
+            return None;
+        }
+        let undo = self.undo.pop_back().expect("just checked");
+        if undo.input.is_some() {
+            self.inputs.pop_back();
+        }
+        Some(undo)
+    }
+
+    /// Forgets everything from the latest checkpoint at or before `step` onwards, returning that
+    /// checkpoint and the inputs read since it, in order.
+    pub(crate) fn rewind(&mut self, step: u64) -> Option<(Snapshot, Vec<i64>)> {
+        let i = self.checkpoints.iter().rposition(|c| c.steps <= step)?;
+        self.checkpoints.truncate(i + 1);
+        let checkpoint = self.checkpoints.pop_back().expect("just found");
+        let from = checkpoint.steps;
+        self.undo.clear();
+        let inputs = self
+            .inputs
+            .iter()
+            .filter(|(s, _)| *s >= from)
+            .map(|(_, v)| *v)
+            .collect();
+        while self.inputs.back().is_some_and(|(s, _)| *s >= from) {
+            self.inputs.pop_back();
+        }
+        Some((checkpoint, inputs))
+    }
+}
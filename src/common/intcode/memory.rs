@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, iter::repeat_n};
+
+/// Number of words in each page of `PagedMemory`.
+pub const PAGE_SIZE: usize = 1024;
+
+/// Which kind of memory an `IntcodeComputer` keeps. Both read unwritten addresses as 0.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MemoryBackend {
+    /// One vector covering every address up to the highest one written. Fastest, but a single
+    /// write far past the end allocates everything in between.
+    #[default]
+    Dense,
+    /// Fixed-size pages allocated as they're written, so the footprint follows what the program
+    /// actually touches.
+    Paged,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Memory {
+    Dense(Vec<i64>),
+    Paged(PagedMemory),
+}
+
+impl Memory {
+    pub fn new(backend: MemoryBackend, program: Vec<i64>) -> Memory {
+        match backend {
+            MemoryBackend::Dense => Memory::Dense(program),
+            MemoryBackend::Paged => Memory::Paged(PagedMemory::from(program)),
+        }
+    }
+
+    /// Builds memory from `(start address, values)` runs, as returned by `segments`.
+    pub fn from_segments<'s>(
+        backend: MemoryBackend,
+        segments: impl IntoIterator<Item = (usize, &'s [i64])>,
+    ) -> Memory {
+        let mut memory = Memory::new(backend, Vec::new());
+        for (start, values) in segments {
+            for (i, v) in values.iter().enumerate() {
+                memory.write(start + i, *v);
+            }
+        }
+        memory
+    }
+
+    pub fn backend(&self) -> MemoryBackend {
+        match self {
+            Memory::Dense(_) => MemoryBackend::Dense,
+            Memory::Paged(_) => MemoryBackend::Paged,
+        }
+    }
+
+    /// The same contents in `backend`'s kind of memory.
+    pub fn into_backend(self, backend: MemoryBackend) -> Memory {
+        match (self, backend) {
+            (Memory::Paged(paged), MemoryBackend::Dense) => {
+                Memory::from_segments(backend, paged.segments())
+            }
+            (Memory::Dense(dense), MemoryBackend::Paged) => Memory::new(backend, dense),
+            (memory, _) => memory,
+        }
+    }
+
+    #[inline]
+    pub fn read(&self, addr: usize) -> i64 {
+        match self {
+            Memory::Dense(dense) => dense.get(addr).copied().unwrap_or(0),
+            Memory::Paged(paged) => paged.read(addr),
+        }
+    }
+
+    /// Like `read`, but `None` past the end of memory.
+    pub fn get(&self, addr: usize) -> Option<i64> {
+        if addr < self.len() {
+            Some(self.read(addr))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn write(&mut self, addr: usize, value: i64) {
+        match self {
+            Memory::Dense(dense) => {
+                if addr >= dense.len() {
+                    dense.extend(repeat_n(0, addr - dense.len() + 1));
+                }
+                dense[addr] = value;
+            }
+            Memory::Paged(paged) => paged.write(addr, value),
+        }
+    }
+
+    /// One past the highest address that's been loaded or written.
+    pub fn len(&self) -> usize {
+        match self {
+            Memory::Dense(dense) => dense.len(),
+            Memory::Paged(paged) => paged.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The allocated parts of memory as `(start address, values)` runs, in address order.
+    /// Everything outside them reads as 0.
+    pub fn segments(&self) -> Vec<(usize, &[i64])> {
+        match self {
+            Memory::Dense(dense) => vec![(0, &dense[..])],
+            Memory::Paged(paged) => paged.segments(),
+        }
+    }
+}
+
+impl From<Vec<i64>> for Memory {
+    fn from(program: Vec<i64>) -> Memory {
+        Memory::Dense(program)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PagedMemory {
+    pages: BTreeMap<usize, Box<[i64]>>,
+    len: usize,
+}
+
+impl PagedMemory {
+    pub fn new() -> PagedMemory {
+        PagedMemory::default()
+    }
+
+    pub fn read(&self, addr: usize) -> i64 {
+        self.pages
+            .get(&(addr / PAGE_SIZE))
+            .map_or(0, |page| page[addr % PAGE_SIZE])
+    }
+
+    /// Writes `value`, allocating its page first unless the value is 0 (which a missing page
+    /// already reads as).
+    pub fn write(&mut self, addr: usize, value: i64) {
+        self.len = self.len.max(addr + 1);
+        let page = addr / PAGE_SIZE;
+        if value == 0 && !self.pages.contains_key(&page) {
+            return;
+        }
+        self.pages
+            .entry(page)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice())[addr % PAGE_SIZE] = value;
+    }
+
+    /// Number of pages allocated so far.
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// One run per allocated page, cut off at the end of memory.
+    pub fn segments(&self) -> Vec<(usize, &[i64])> {
+        self.pages
+            .iter()
+            .map(|(page, words)| {
+                let start = page * PAGE_SIZE;
+                (start, &words[..(self.len - start).min(PAGE_SIZE)])
+            })
+            .collect()
+    }
+}
+
+impl From<Vec<i64>> for PagedMemory {
+    fn from(program: Vec<i64>) -> PagedMemory {
+        let mut memory = PagedMemory::new();
+        for (addr, value) in program.into_iter().enumerate() {
+            memory.write(addr, value);
+        }
+        memory
+    }
+}
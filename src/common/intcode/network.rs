@@ -0,0 +1,294 @@
+//! Intcode machines running on their own threads, talking over `mpsc` channels.
+
+use super::{IntcodeComputer, IntcodeError, State, ports::InputSource};
+
+use std::{
+    sync::{
+        Arc, Condvar, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Runs `program` on a new thread, reading from `input` and writing to `output`. The machine
+/// stops with `State::BlockedOnInput` once `input` is disconnected and empty.
+pub fn spawn(
+    program: Vec<i64>,
+    input: Receiver<i64>,
+    output: Sender<i64>,
+) -> JoinHandle<Result<State, IntcodeError>> {
+    thread::spawn(move || {
+        let mut c = IntcodeComputer::new(program)
+            .with_input(input)
+            .with_output(output);
+        c.run()?;
+        Ok(c.get_state())
+    })
+}
+
+/// A set of machines whose outputs are wired to other machines' inputs, e.g. day 7's amplifiers:
+///
+/// ```text
+/// let report = Network::ring(vec![program; 5])
+///     .input(0, [9, 0])
+///     .input(1, [8])
+///     ...
+///     .run();
+/// ```
+///
+/// Each machine runs on its own thread. Everything a machine outputs is sent to every machine it's
+/// linked to, and a machine linked to from several others reads their values in the order they
+/// arrive. Input given with `input` is read before anything from links.
+///
+/// If every machine still running is waiting on an empty channel, none of them can ever make
+/// progress; they're all stopped with `State::BlockedOnInput` and the run is reported as
+/// deadlocked.
+#[derive(Clone, Debug, Default)]
+pub struct Network {
+    programs: Vec<Vec<i64>>,
+    inputs: Vec<Vec<i64>>,
+    links: Vec<(usize, usize)>,
+}
+
+/// How one machine in a `Network` finished.
+#[derive(Debug)]
+pub struct MachineReport {
+    /// Everything the machine output, whether or not it was linked anywhere.
+    pub outputs: Vec<i64>,
+    pub result: Result<State, IntcodeError>,
+}
+
+#[derive(Debug)]
+pub struct NetworkReport {
+    /// Indexed like the machines were added.
+    pub machines: Vec<MachineReport>,
+    pub deadlocked: bool,
+}
+
+impl NetworkReport {
+    pub fn outputs(&self, machine: usize) -> &[i64] {
+        &self.machines[machine].outputs
+    }
+
+    pub fn last_output(&self, machine: usize) -> Option<i64> {
+        self.outputs(machine).last().copied()
+    }
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Network::default()
+    }
+
+    /// Machines running `programs`, each feeding the next.
+    pub fn chain(programs: impl IntoIterator<Item = Vec<i64>>) -> Self {
+        let mut network = Network::new();
+        for program in programs {
+            network = network.machine(program);
+        }
+        for i in 1..network.programs.len() {
+            network = network.link(i - 1, i);
+        }
+        network
+    }
+
+    /// A `chain` with the last machine feeding back into the first.
+    pub fn ring(programs: impl IntoIterator<Item = Vec<i64>>) -> Self {
+        let network = Network::chain(programs);
+        match network.programs.len() {
+            0 => network,
+            n => network.link(n - 1, 0),
+        }
+    }
+
+    /// Adds a machine, numbered from 0 in the order they're added.
+    pub fn machine(mut self, program: Vec<i64>) -> Self {
+        self.programs.push(program);
+        self.inputs.push(Vec::new());
+        self
+    }
+
+    /// Sends `from`'s output to `to`.
+    pub fn link(mut self, from: usize, to: usize) -> Self {
+        assert!(
+            from < self.programs.len() && to < self.programs.len(),
+            "no such machine"
+        );
+        self.links.push((from, to));
+        self
+    }
+
+    /// Queues input for `machine`, ahead of anything from its links.
+    pub fn input(mut self, machine: usize, values: impl IntoIterator<Item = i64>) -> Self {
+        self.inputs[machine].extend(values);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Runs every machine until it halts, fails or deadlocks.
+    pub fn run(self) -> NetworkReport {
+        let n = self.programs.len();
+        let monitor = Arc::new(Monitor {
+            counts: Mutex::new(Counts {
+                running: n,
+                blocked: 0,
+                pending: self.inputs.iter().map(|i| i.len()).collect(),
+                finished: vec![false; n],
+                deadlocked: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..n).map(|_| mpsc::channel()).unzip();
+        for (sender, inputs) in senders.iter().zip(&self.inputs) {
+            for value in inputs {
+                sender.send(*value).expect("receiver is still here");
+            }
+        }
+
+        let handles: Vec<_> = self
+            .programs
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(id, (program, rx))| {
+                let targets = self
+                    .links
+                    .iter()
+                    .filter(|(from, _)| *from == id)
+                    .map(|(_, to)| (*to, senders[*to].clone()))
+                    .collect();
+                let input = Port {
+                    id,
+                    rx,
+                    monitor: Arc::clone(&monitor),
+                };
+                let output = Links {
+                    targets,
+                    monitor: Arc::clone(&monitor),
+                };
+                thread::spawn(move || run_machine(id, program, input, output))
+            })
+            .collect();
+        drop(senders);
+
+        let machines = handles
+            .into_iter()
+            .map(|h| h.join().expect("machine thread panicked"))
+            .collect();
+        let deadlocked = monitor.counts.lock().expect("poisoned").deadlocked;
+        NetworkReport {
+            machines,
+            deadlocked,
+        }
+    }
+}
+
+fn run_machine(id: usize, program: Vec<i64>, input: Port, mut output: Links) -> MachineReport {
+    let monitor = Arc::clone(&input.monitor);
+    let mut outputs = Vec::new();
+    let result = {
+        let mut c = IntcodeComputer::new(program)
+            .with_input(input)
+            .with_output(|v| {
+                output.send(v);
+                outputs.push(v);
+            });
+        c.run().map(|_| c.get_state())
+    };
+
+    let mut counts = monitor.counts.lock().expect("poisoned");
+    counts.running -= 1;
+    counts.finished[id] = true;
+    counts.pending[id] = 0;
+    counts.check_deadlock();
+    monitor.changed.notify_all();
+    drop(counts);
+
+    MachineReport { outputs, result }
+}
+
+/// Bookkeeping shared by all the machines in a network, for spotting deadlock.
+struct Monitor {
+    counts: Mutex<Counts>,
+    changed: Condvar,
+}
+
+struct Counts {
+    running: usize,
+    /// Machines waiting on an empty channel.
+    blocked: usize,
+    /// Values sent to each machine that it hasn't read yet.
+    pending: Vec<usize>,
+    finished: Vec<bool>,
+    deadlocked: bool,
+}
+
+impl Counts {
+    fn check_deadlock(&mut self) {
+        // A blocked machine might have been sent something and not be awake to read it yet.
+        if self.running > 0 && self.blocked == self.running && self.pending.iter().all(|p| *p == 0)
+        {
+            self.deadlocked = true;
+        }
+    }
+}
+
+/// A machine's input end of the network.
+struct Port {
+    id: usize,
+    rx: Receiver<i64>,
+    monitor: Arc<Monitor>,
+}
+
+impl InputSource for Port {
+    fn next_input(&mut self) -> Option<i64> {
+        let mut counts = self.monitor.counts.lock().expect("poisoned");
+        loop {
+            // Values are only sent with the lock held, so `pending` and the channel agree here.
+            if let Ok(value) = self.rx.try_recv() {
+                counts.pending[self.id] -= 1;
+                return Some(value);
+            }
+            if counts.deadlocked {
+                return None;
+            }
+
+            counts.blocked += 1;
+            counts.check_deadlock();
+            if counts.deadlocked {
+                counts.blocked -= 1;
+                self.monitor.changed.notify_all();
+                return None;
+            }
+            counts = self.monitor.changed.wait(counts).expect("poisoned");
+            counts.blocked -= 1;
+        }
+    }
+}
+
+/// A machine's output end of the network.
+struct Links {
+    targets: Vec<(usize, Sender<i64>)>,
+    monitor: Arc<Monitor>,
+}
+
+impl Links {
+    fn send(&mut self, value: i64) {
+        let mut counts = self.monitor.counts.lock().expect("poisoned");
+        for (to, sender) in &self.targets {
+            // A machine that's finished will never read it.
+            if !counts.finished[*to] && sender.send(value).is_ok() {
+                counts.pending[*to] += 1;
+            }
+        }
+        self.monitor.changed.notify_all();
+    }
+}
@@ -0,0 +1,79 @@
+use super::Opcode;
+
+use std::ops::Range;
+
+/// Gets told about everything an `IntcodeComputer` does, as it does it. `instr` is always the
+/// address of the instruction responsible. Every method does nothing by default.
+pub trait Observer {
+    /// An instruction has been decoded and is about to run. Blocked I/O instructions are fetched
+    /// again each time they're retried.
+    fn fetch(&mut self, _instr: usize, _opcode: Opcode) {}
+
+    /// An operand was read from memory. Instruction fetches aren't reported here.
+    fn read(&mut self, _instr: usize, _addr: usize, _value: i64) {}
+
+    fn write(&mut self, _instr: usize, _addr: usize, _old: i64, _new: i64) {}
+
+    fn relative_base(&mut self, _instr: usize, _old: i64, _new: i64) {}
+
+    fn input(&mut self, _instr: usize, _value: i64) {}
+
+    fn output(&mut self, _instr: usize, _value: i64) {}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn covers(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+/// Pauses the computer with `State::WatchpointHit` after an instruction that reads or writes an
+/// address in `range`.
+pub struct Watchpoint<'a> {
+    pub range: Range<usize>,
+    pub access: Access,
+    condition: Option<Box<dyn Fn(i64) -> bool + 'a>>,
+}
+
+impl<'a> Watchpoint<'a> {
+    pub fn new(range: Range<usize>, access: Access) -> Self {
+        Watchpoint {
+            range,
+            access,
+            condition: None,
+        }
+    }
+
+    /// Only triggers when `condition` holds for the value read or written.
+    pub fn when(mut self, condition: impl Fn(i64) -> bool + 'a) -> Self {
+        self.condition = Some(Box::new(condition));
+        self
+    }
+
+    pub(super) fn matches(&self, addr: usize, access: Access, value: i64) -> bool {
+        self.range.contains(&addr)
+            && self.access.covers(access)
+            && self.condition.as_ref().is_none_or(|c| c(value))
+    }
+}
+
+/// The access that triggered a watchpoint.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    /// As returned by `add_watchpoint`.
+    pub id: usize,
+    pub instr: usize,
+    pub addr: usize,
+    /// `Read` or `Write`.
+    pub access: Access,
+    /// The value before a write.
+    pub old: Option<i64>,
+    pub value: i64,
+}
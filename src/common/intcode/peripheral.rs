@@ -0,0 +1,117 @@
+//! Devices that read an Intcode program's output as fixed-size frames, e.g. day 13's
+//! `(x, y, tile)` triples, and decode them into typed events.
+
+use super::{IntcodeComputer, IntcodeError, State};
+
+use std::{error::Error, fmt, mem};
+
+/// The shape of a device's frames and how to decode them.
+pub trait Device {
+    type Event;
+
+    /// How many output values make up one frame.
+    const FRAME_LEN: usize;
+
+    /// Decodes one complete frame, adding its events to `events`.
+    fn decode(&mut self, frame: &[i64], events: &mut Vec<Self::Event>) -> Result<(), String>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeripheralError {
+    /// A complete frame the device couldn't decode.
+    Malformed {
+        frame: Vec<i64>,
+        msg: String,
+    },
+    /// The program halted partway through a frame.
+    Truncated(Vec<i64>),
+    Intcode(IntcodeError),
+}
+
+impl fmt::Display for PeripheralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeripheralError::Malformed { frame, msg } => {
+                write!(f, "malformed frame {:?}: {}", frame, msg)
+            }
+            PeripheralError::Truncated(frame) => write!(f, "truncated frame {:?}", frame),
+            PeripheralError::Intcode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for PeripheralError {}
+
+impl From<IntcodeError> for PeripheralError {
+    fn from(e: IntcodeError) -> Self {
+        PeripheralError::Intcode(e)
+    }
+}
+
+/// A `Device` attached to a computer's output. Frames may be split across calls, e.g. when the
+/// program stops for input in the middle of one.
+///
+/// The computer shouldn't have an output sink, or there'll be nothing to decode.
+pub struct Peripheral<D: Device> {
+    device: D,
+    partial: Vec<i64>,
+}
+
+impl<D: Device> Peripheral<D> {
+    pub fn new(device: D) -> Self {
+        Peripheral {
+            device,
+            partial: Vec::with_capacity(D::FRAME_LEN),
+        }
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Runs `c` until it halts or needs more input, decoding everything it outputs. Fails with
+    /// `Truncated` if it halts partway through a frame.
+    pub fn poll(&mut self, c: &mut IntcodeComputer) -> Result<Vec<D::Event>, PeripheralError> {
+        let state = c.run_until_blocked()?;
+        let events = self.feed(c.drain_output())?;
+        if state == State::Terminated {
+            self.finish()?;
+        }
+        Ok(events)
+    }
+
+    /// Decodes raw output values, returning the events from every frame they complete.
+    pub fn feed(
+        &mut self,
+        values: impl IntoIterator<Item = i64>,
+    ) -> Result<Vec<D::Event>, PeripheralError> {
+        let mut events = Vec::new();
+        for value in values {
+            self.partial.push(value);
+            if self.partial.len() == D::FRAME_LEN {
+                let frame = mem::replace(&mut self.partial, Vec::with_capacity(D::FRAME_LEN));
+                self.device
+                    .decode(&frame, &mut events)
+                    .map_err(|msg| PeripheralError::Malformed { frame, msg })?;
+            }
+        }
+        Ok(events)
+    }
+
+    /// Fails with `Truncated` if part of a frame has been read but not the rest.
+    pub fn finish(&mut self) -> Result<(), PeripheralError> {
+        if self.partial.is_empty() {
+            Ok(())
+        } else {
+            Err(PeripheralError::Truncated(mem::take(&mut self.partial)))
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::mpsc::{Receiver, Sender},
+};
+
+/// Somewhere an `IntcodeComputer` reads its input from. Returning `None` means the source has
+/// nothing to give right now; what the computer does then is decided by its `InputPolicy`.
+pub trait InputSource {
+    fn next_input(&mut self) -> Option<i64>;
+}
+
+/// Somewhere an `IntcodeComputer` writes its output to.
+pub trait OutputSink {
+    fn output(&mut self, value: i64) -> io::Result<()>;
+}
+
+impl<F: FnMut() -> i64> InputSource for F {
+    fn next_input(&mut self) -> Option<i64> {
+        Some(self())
+    }
+}
+
+impl<F: FnMut(i64)> OutputSink for F {
+    fn output(&mut self, value: i64) -> io::Result<()> {
+        self(value);
+        Ok(())
+    }
+}
+
+impl InputSource for VecDeque<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl OutputSink for VecDeque<i64> {
+    fn output(&mut self, value: i64) -> io::Result<()> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl OutputSink for Vec<i64> {
+    fn output(&mut self, value: i64) -> io::Result<()> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// Blocks until a value arrives; a disconnected channel has nothing to give.
+impl InputSource for Receiver<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+/// Like a plain `Receiver`, but reports "nothing yet" instead of waiting for a value.
+pub struct TryRecvInput(pub Receiver<i64>);
+
+impl InputSource for TryRecvInput {
+    fn next_input(&mut self) -> Option<i64> {
+        self.0.try_recv().ok()
+    }
+}
+
+impl OutputSink for Sender<i64> {
+    fn output(&mut self, value: i64) -> io::Result<()> {
+        self.send(value)
+            .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))
+    }
+}
+
+/// What an `IntcodeComputer` does when its input source has nothing to give.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputPolicy {
+    /// Stop with `State::BlockedOnInput`, leaving the instruction to be retried (or completed
+    /// with `provide_input`).
+    Block,
+    /// Read this value instead.
+    Default(i64),
+    /// Fail with `IntcodeErrorKind::InputExhausted`.
+    Fail,
+}
+
+/// Feeds the values of any iterator as input.
+pub struct IterInput<I: Iterator<Item = i64>>(pub I);
+
+impl<I: Iterator<Item = i64>> InputSource for IterInput<I> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.0.next()
+    }
+}
+
+/// Writes each output value on its own line.
+pub struct WriteOutput<W: Write>(pub W);
+
+impl<W: Write> OutputSink for WriteOutput<W> {
+    fn output(&mut self, value: i64) -> io::Result<()> {
+        writeln!(self.0, "{}", value)
+    }
+}
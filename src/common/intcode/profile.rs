@@ -0,0 +1,187 @@
+use super::{Opcode, disasm, memory::Memory, registry::OpcodeRegistry};
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, Write},
+};
+
+/// Execution counts gathered by an `IntcodeComputer` with profiling turned on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// Completed instructions by address.
+    pub executions: HashMap<usize, u64>,
+    pub opcodes: HashMap<Opcode, u64>,
+    /// Operand reads by address (instruction fetches aren't counted).
+    pub reads: HashMap<usize, u64>,
+    /// Writes made by the program by address.
+    pub writes: HashMap<usize, u64>,
+    /// `(taken, not taken)` by address of the jump instruction.
+    pub jumps: HashMap<usize, (u64, u64)>,
+}
+
+/// One address's counts alongside the disassembly of whatever's there now.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportRow {
+    pub addr: usize,
+    pub executions: u64,
+    pub reads: u64,
+    pub writes: u64,
+    pub jumps_taken: u64,
+    pub jumps_not_taken: u64,
+    pub line: disasm::Line,
+}
+
+impl Profile {
+    pub fn total(&self) -> u64 {
+        self.executions.values().sum()
+    }
+
+    pub(super) fn executed(&mut self, addr: usize, opcode: Opcode) {
+        *self.executions.entry(addr).or_default() += 1;
+        *self.opcodes.entry(opcode).or_default() += 1;
+    }
+
+    pub(super) fn read(&mut self, addr: usize) {
+        *self.reads.entry(addr).or_default() += 1;
+    }
+
+    pub(super) fn wrote(&mut self, addr: usize) {
+        *self.writes.entry(addr).or_default() += 1;
+    }
+
+    pub(super) fn jump(&mut self, addr: usize, taken: bool) {
+        let (t, n) = self.jumps.entry(addr).or_default();
+        if taken {
+            *t += 1;
+        } else {
+            *n += 1;
+        }
+    }
+
+    /// A row for every address that was executed, read or written, hottest first. Executed
+    /// addresses are disassembled from `memory` as it is now, so self-modified code shows its
+    /// final form; the rest are shown as data. `opcodes` should be the computer's, so that
+    /// registered instructions are recognised.
+    pub fn report(&self, memory: &Memory, opcodes: &OpcodeRegistry) -> Vec<ReportRow> {
+        let addrs: BTreeSet<usize> = self
+            .executions
+            .keys()
+            .chain(self.reads.keys())
+            .chain(self.writes.keys())
+            .copied()
+            .collect();
+        let count = |counts: &HashMap<usize, u64>, addr| counts.get(&addr).copied().unwrap_or(0);
+
+        let mut rows: Vec<ReportRow> = addrs
+            .into_iter()
+            .map(|addr| {
+                let (jumps_taken, jumps_not_taken) =
+                    self.jumps.get(&addr).copied().unwrap_or((0, 0));
+                let executions = count(&self.executions, addr);
+                let line = if executions > 0 {
+                    disasm::line_with(memory, addr, opcodes)
+                } else {
+                    disasm::Line::Data {
+                        addr,
+                        value: memory.read(addr),
+                    }
+                };
+                ReportRow {
+                    addr,
+                    executions,
+                    reads: count(&self.reads, addr),
+                    writes: count(&self.writes, addr),
+                    jumps_taken,
+                    jumps_not_taken,
+                    line,
+                }
+            })
+            .collect();
+        rows.sort_by_key(|r| {
+            (
+                u64::MAX - r.executions,
+                u64::MAX - (r.reads + r.writes),
+                r.addr,
+            )
+        });
+        rows
+    }
+
+    /// Per-opcode totals followed by the per-address report as aligned columns.
+    pub fn write_text<W: Write>(
+        &self,
+        memory: &Memory,
+        opcodes: &OpcodeRegistry,
+        mut w: W,
+    ) -> io::Result<()> {
+        let total = self.total();
+        let percent = |n: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                n as f64 * 100.0 / total as f64
+            }
+        };
+
+        writeln!(w, "{} instructions executed", total)?;
+        writeln!(w)?;
+        let mut by_opcode: Vec<_> = self.opcodes.iter().collect();
+        by_opcode.sort_by_key(|(o, n)| (u64::MAX - **n, o.mnemonic()));
+        writeln!(w, "{:<6}{:>12}{:>9}", "op", "count", "%")?;
+        for (opcode, n) in by_opcode {
+            writeln!(w, "{:<6}{:>12}{:>8.2}%", opcode.mnemonic(), n, percent(*n))?;
+        }
+
+        writeln!(w)?;
+        writeln!(
+            w,
+            "{:>12}{:>9}{:>10}{:>10}{:>16}  instruction",
+            "executions", "%", "reads", "writes", "taken/not"
+        )?;
+        for row in self.report(memory, opcodes) {
+            let jumps = if row.jumps_taken + row.jumps_not_taken > 0 {
+                format!("{}/{}", row.jumps_taken, row.jumps_not_taken)
+            } else {
+                String::new()
+            };
+            writeln!(
+                w,
+                "{:>12}{:>8.2}%{:>10}{:>10}{:>16}  {}",
+                row.executions,
+                percent(row.executions),
+                row.reads,
+                row.writes,
+                jumps,
+                row.line
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The per-address report as CSV, with a header row.
+    pub fn write_csv<W: Write>(
+        &self,
+        memory: &Memory,
+        opcodes: &OpcodeRegistry,
+        mut w: W,
+    ) -> io::Result<()> {
+        writeln!(
+            w,
+            "addr,executions,reads,writes,jumps_taken,jumps_not_taken,instruction"
+        )?;
+        for row in self.report(memory, opcodes) {
+            writeln!(
+                w,
+                "{},{},{},{},{},{},\"{}\"",
+                row.addr,
+                row.executions,
+                row.reads,
+                row.writes,
+                row.jumps_taken,
+                row.jumps_not_taken,
+                row.line.text()
+            )?;
+        }
+        Ok(())
+    }
+}
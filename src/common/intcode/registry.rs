@@ -0,0 +1,191 @@
+//! Extra opcodes for experimental Intcode dialects, on top of the standard 2019 set.
+//!
+//! ```text
+//! let opcodes = OpcodeRegistry::new().register(
+//!     CustomOpcode::new(20, "SWP", &[Role::Write, Role::Write]),
+//!     |x| {
+//!         let (a, b) = (x.read(x.arg(1))?, x.read(x.arg(2))?);
+//!         x.set(1, b)?;
+//!         x.set(2, a)
+//!     },
+//! );
+//! let mut c = IntcodeComputer::new(program).with_opcodes(opcodes);
+//! ```
+//!
+//! A registered opcode decodes to `Opcode::Custom`, which carries its mnemonic and parameter
+//! roles, so tracing and disassembly with `disasm::line_with` show it like any other instruction.
+
+use super::{IntcodeComputer, IntcodeError, IntcodeErrorKind, MAX_PARAMS, OPCODE_NUMBERS, Opcode};
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// What an instruction does with one of its parameters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    Read,
+    /// Written through, so immediate mode isn't allowed.
+    Write,
+}
+
+/// The shape of a registered opcode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomOpcode {
+    pub number: i64,
+    pub mnemonic: &'static str,
+    arity: usize,
+    roles: [Role; MAX_PARAMS],
+}
+
+impl CustomOpcode {
+    /// Panics if `number` isn't a free two-digit opcode or there are more than three parameters.
+    pub fn new(number: i64, mnemonic: &'static str, roles: &[Role]) -> Self {
+        assert!(
+            (1..100).contains(&number) && !OPCODE_NUMBERS.contains(&number),
+            "opcode {} isn't free",
+            number
+        );
+        assert!(roles.len() <= MAX_PARAMS, "too many parameters");
+        let mut padded = [Role::Read; MAX_PARAMS];
+        padded[..roles.len()].copy_from_slice(roles);
+        CustomOpcode {
+            number,
+            mnemonic,
+            arity: roles.len(),
+            roles: padded,
+        }
+    }
+
+    pub fn num_params(&self) -> usize {
+        self.arity
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles[..self.arity]
+    }
+}
+
+type Execute = dyn Fn(&mut Exec) -> Result<(), IntcodeError> + Send + Sync;
+
+/// The opcodes an `IntcodeComputer` understands. The default is just the standard set.
+#[derive(Clone, Default)]
+pub struct OpcodeRegistry {
+    custom: HashMap<i64, (CustomOpcode, Arc<Execute>)>,
+}
+
+impl fmt::Debug for OpcodeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ops: Vec<_> = self.custom.values().map(|(op, _)| op).collect();
+        ops.sort_by_key(|op| op.number);
+        f.debug_struct("OpcodeRegistry")
+            .field("custom", &ops)
+            .finish()
+    }
+}
+
+impl OpcodeRegistry {
+    pub fn new() -> Self {
+        OpcodeRegistry::default()
+    }
+
+    /// Adds `opcode`, run by `execute`, replacing anything already registered with its number.
+    pub fn register(
+        mut self,
+        opcode: CustomOpcode,
+        execute: impl Fn(&mut Exec) -> Result<(), IntcodeError> + Send + Sync + 'static,
+    ) -> Self {
+        self.custom
+            .insert(opcode.number, (opcode, Arc::new(execute)));
+        self
+    }
+
+    /// The opcode numbered `number`, standard or registered.
+    pub fn opcode(&self, number: i64) -> Option<Opcode> {
+        Opcode::try_from(number)
+            .ok()
+            .or_else(|| self.custom.get(&number).map(|(op, _)| Opcode::Custom(*op)))
+    }
+
+    pub fn from_mnemonic(&self, mnemonic: &str) -> Option<Opcode> {
+        Opcode::from_mnemonic(mnemonic).or_else(|| {
+            self.custom
+                .values()
+                .find(|(op, _)| op.mnemonic.eq_ignore_ascii_case(mnemonic))
+                .map(|(op, _)| Opcode::Custom(*op))
+        })
+    }
+
+    pub(super) fn execute(&self, number: i64) -> Option<Arc<Execute>> {
+        self.custom
+            .get(&number)
+            .map(|(_, execute)| Arc::clone(execute))
+    }
+}
+
+/// A registered instruction's view of the machine while it runs. Reads and writes go through
+/// the computer as the standard instructions' do, so observers, watchpoints, the profiler and
+/// history all see them.
+pub struct Exec<'c, 'a> {
+    pub(super) computer: &'c mut IntcodeComputer<'a>,
+    /// Resolved parameters: values for `Read`, addresses for `Write`.
+    pub(super) args: [i64; MAX_PARAMS],
+    pub(super) write: Option<(usize, i64)>,
+    pub(super) relative_base: Option<(i64, i64)>,
+    pub(super) jump: Option<usize>,
+}
+
+impl Exec<'_, '_> {
+    /// The `i`th (1-based) parameter: its value if it's read, or the address it refers to if
+    /// it's written.
+    pub fn arg(&self, i: usize) -> i64 {
+        self.args[i - 1]
+    }
+
+    /// Writes `value` to the address of the `i`th parameter.
+    pub fn set(&mut self, i: usize, value: i64) -> Result<(), IntcodeError> {
+        self.write(self.args[i - 1], value)
+    }
+
+    pub fn read(&mut self, addr: i64) -> Result<i64, IntcodeError> {
+        self.computer.get_mem(addr)
+    }
+
+    /// Only writes through `Write` parameters are checked against the budget's memory limit.
+    pub fn write(&mut self, addr: i64, value: i64) -> Result<(), IntcodeError> {
+        let addr = self.computer.to_address(addr)?;
+        self.computer.store(addr, value);
+        self.write.get_or_insert((addr, value));
+        Ok(())
+    }
+
+    /// The address of the instruction being run.
+    pub fn ip(&self) -> usize {
+        self.computer.instr
+    }
+
+    /// Carries on at `addr` instead of the next instruction.
+    pub fn jump(&mut self, addr: i64) -> Result<(), IntcodeError> {
+        self.jump = Some(self.computer.to_address(addr)?);
+        Ok(())
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.computer.relative_base
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: i64) {
+        let c = &mut *self.computer;
+        let (instr, old) = (c.instr, c.relative_base);
+        c.relative_base = relative_base;
+        if let Some(history) = &mut c.history {
+            history.relative_base(old);
+        }
+        c.observe(|o| o.relative_base(instr, old, relative_base));
+        let first = self.relative_base.map_or(old, |(first, _)| first);
+        self.relative_base = Some((first, relative_base));
+    }
+
+    /// An error for the instruction failing, e.g. on a division by zero.
+    pub fn fail(&self, msg: &'static str) -> IntcodeError {
+        self.computer.error(IntcodeErrorKind::Custom(msg))
+    }
+}
@@ -0,0 +1,240 @@
+//! Recordings of everything a computer read and wrote, for reproducing a session exactly.
+
+use super::{
+    IntcodeComputer, IntcodeError, State,
+    text::{invalid, parse},
+};
+
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+const HEADER: &str = "intcode-replay 1";
+
+/// One input or output, with the step number (as counted by `IntcodeComputer::steps`) and
+/// address of the instruction that did it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IoEvent {
+    Input { step: u64, instr: usize, value: i64 },
+    Output { step: u64, instr: usize, value: i64 },
+}
+
+impl IoEvent {
+    pub fn step(&self) -> u64 {
+        match self {
+            IoEvent::Input { step, .. } | IoEvent::Output { step, .. } => *step,
+        }
+    }
+}
+
+impl fmt::Display for IoEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (what, step, instr, value) = match self {
+            IoEvent::Input { step, instr, value } => ("input", step, instr, value),
+            IoEvent::Output { step, instr, value } => ("output", step, instr, value),
+        };
+        write!(f, "{} {} at step {} (ip {})", what, value, step, instr)
+    }
+}
+
+/// The I/O of a session, kept by `IntcodeComputer::with_recording`.
+///
+/// Saved recordings are plain text, one event per line as `in|out STEP IP VALUE`:
+///
+/// ```text
+/// intcode-replay 1
+/// out 0 0 109
+/// in 9 14 -1
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    pub events: Vec<IoEvent>,
+}
+
+impl Recording {
+    pub fn inputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.events.iter().filter_map(|e| match e {
+            IoEvent::Input { value, .. } => Some(*value),
+            IoEvent::Output { .. } => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.events.iter().filter_map(|e| match e {
+            IoEvent::Output { value, .. } => Some(*value),
+            IoEvent::Input { .. } => None,
+        })
+    }
+
+    pub(crate) fn push(&mut self, event: IoEvent) {
+        self.events.push(event);
+    }
+
+    /// Drops everything from step `step` on, after the computer has been wound back.
+    pub(crate) fn forget_from(&mut self, step: u64) {
+        while self.events.last().is_some_and(|e| e.step() >= step) {
+            self.events.pop();
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Recording> {
+        Recording::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{}", HEADER)?;
+        for event in &self.events {
+            match event {
+                IoEvent::Input { step, instr, value } => {
+                    writeln!(w, "in {} {} {}", step, instr, value)?
+                }
+                IoEvent::Output { step, instr, value } => {
+                    writeln!(w, "out {} {} {}", step, instr, value)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(r: R) -> io::Result<Recording> {
+        let mut lines = r.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid(format!("expected '{}' header", HEADER)));
+        }
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let event = match fields[..] {
+                [] => continue,
+                ["in", step, instr, value] => IoEvent::Input {
+                    step: parse(step)?,
+                    instr: parse(instr)?,
+                    value: parse(value)?,
+                },
+                ["out", step, instr, value] => IoEvent::Output {
+                    step: parse(step)?,
+                    instr: parse(instr)?,
+                    value: parse(value)?,
+                },
+                _ => return Err(invalid(format!("bad event '{}'", line))),
+            };
+            events.push(event);
+        }
+        Ok(Recording { events })
+    }
+}
+
+/// What happened instead of the next recorded event.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// A different input or output, or the same one at a different step or address.
+    Event(IoEvent),
+    /// The program asked for input when the recording has something else next, so the value it
+    /// would have read isn't known.
+    WantedInput,
+    Halted,
+    /// The program stopped some other way, e.g. on a watchpoint or budget.
+    Stopped(State),
+    Failed(Box<IntcodeError>),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Event(e) => write!(f, "{}", e),
+            Mismatch::WantedInput => write!(f, "a request for input"),
+            Mismatch::Halted => write!(f, "halt"),
+            Mismatch::Stopped(s) => write!(f, "stop in state {:?}", s),
+            Mismatch::Failed(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+/// The first point at which a replayed session stopped matching its recording.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub step: u64,
+    pub instr: usize,
+    /// `None` if the recording had ended.
+    pub expected: Option<IoEvent>,
+    pub actual: Mismatch,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "diverged at step {} (ip {}): expected ",
+            self.step, self.instr
+        )?;
+        match &self.expected {
+            Some(e) => write!(f, "{}", e)?,
+            None => write!(f, "the end of the recording")?,
+        }
+        write!(f, ", got {}", self.actual)
+    }
+}
+
+impl Error for Divergence {}
+
+/// Runs `c` to completion, giving it the recorded inputs and checking that every input and
+/// output happens exactly as recorded. `c` should be set up as it was for the recording (same
+/// program, patches and opcodes) and have no I/O attached.
+pub fn verify(c: &mut IntcodeComputer, recording: &Recording) -> Result<(), Divergence> {
+    let mut expected = recording.events.iter().copied();
+    loop {
+        let result = c.run();
+        let (step, instr) = (c.steps(), c.get_instr());
+        let next = expected.next();
+        let diverged = |actual| Divergence {
+            step,
+            instr,
+            expected: next,
+            actual,
+        };
+        if let Err(e) = result {
+            return Err(diverged(Mismatch::Failed(Box::new(e))));
+        }
+
+        match c.get_state() {
+            State::BlockedOnInput => match next {
+                Some(IoEvent::Input {
+                    step: s,
+                    instr: i,
+                    value,
+                }) if s == step && i == instr => c
+                    .provide_input(value)
+                    .map_err(|e| diverged(Mismatch::Failed(Box::new(e))))?,
+                _ => return Err(diverged(Mismatch::WantedInput)),
+            },
+            State::BlockedOnOutput => {
+                let value = c
+                    .get_output()
+                    .map_err(|e| diverged(Mismatch::Failed(Box::new(e))))?;
+                let actual = IoEvent::Output { step, instr, value };
+                if next != Some(actual) {
+                    return Err(diverged(Mismatch::Event(actual)));
+                }
+            }
+            State::Terminated => {
+                return match next {
+                    None => Ok(()),
+                    Some(_) => Err(diverged(Mismatch::Halted)),
+                };
+            }
+            state => return Err(diverged(Mismatch::Stopped(state))),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::{
+    Opcode, ParameterMode, State,
+    memory::MemoryBackend,
+    registry::OpcodeRegistry,
+    text::{invalid, parse},
+};
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+const HEADER: &str = "intcode-snapshot 1";
+
+/// The complete execution state of an `IntcodeComputer`, minus its I/O closures and trace sink.
+///
+/// Saved snapshots are plain text, one `key value` pair per line:
+///
+/// ```text
+/// intcode-snapshot 1
+/// ip 12
+/// relative_base 0
+/// steps 1234
+/// opcode IN
+/// pmodes 2
+/// state BlockedOnInput
+/// memory_backend Paged
+/// memory 109,1,203,-1,...
+/// memory@1048576 0,0,7
+/// input_queue 5,6
+/// output_buffer
+/// ```
+///
+/// `memory` holds the words from address 0 and each `memory@ADDR` line another run starting at
+/// `ADDR`; anything not listed is 0. `memory_backend`, `input_queue` and `output_buffer` may be
+/// left out, in which case they're `Dense`, empty and empty; a missing `steps` is 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// `(start address, values)` runs, in address order.
+    pub memory: Vec<(usize, Vec<i64>)>,
+    pub memory_backend: MemoryBackend,
+    pub instr: usize,
+    pub relative_base: i64,
+    pub steps: u64,
+    pub opcode: Opcode,
+    pub pmodes: Vec<ParameterMode>,
+    pub state: State,
+    pub input_queue: Vec<i64>,
+    pub output_buffer: Vec<i64>,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Snapshot> {
+        Snapshot::load_with(path, &OpcodeRegistry::default())
+    }
+
+    /// `load`, also recognising the opcodes registered in `opcodes`.
+    pub fn load_with(path: impl AsRef<Path>, opcodes: &OpcodeRegistry) -> io::Result<Snapshot> {
+        Snapshot::read_with(BufReader::new(File::open(path)?), opcodes)
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{}", HEADER)?;
+        writeln!(w, "ip {}", self.instr)?;
+        writeln!(w, "relative_base {}", self.relative_base)?;
+        writeln!(w, "steps {}", self.steps)?;
+        writeln!(w, "opcode {}", self.opcode.mnemonic())?;
+        writeln!(
+            w,
+            "pmodes {}",
+            join(self.pmodes.iter().map(|m| i64::from(*m)))
+        )?;
+        writeln!(w, "state {:?}", self.state)?;
+        writeln!(w, "memory_backend {:?}", self.memory_backend)?;
+        let mut segments = self.memory.iter().peekable();
+        match segments.next_if(|(start, _)| *start == 0) {
+            Some((_, values)) => writeln!(w, "memory {}", join(values.iter()))?,
+            None => writeln!(w, "memory")?,
+        }
+        for (start, values) in segments {
+            writeln!(w, "memory@{} {}", start, join(values.iter()))?;
+        }
+        writeln!(w, "input_queue {}", join(self.input_queue.iter()))?;
+        writeln!(w, "output_buffer {}", join(self.output_buffer.iter()))
+    }
+
+    pub fn read_from<R: BufRead>(r: R) -> io::Result<Snapshot> {
+        Snapshot::read_with(r, &OpcodeRegistry::default())
+    }
+
+    /// `read_from`, also recognising the opcodes registered in `opcodes`.
+    pub fn read_with<R: BufRead>(r: R, opcodes: &OpcodeRegistry) -> io::Result<Snapshot> {
+        let mut lines = r.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid(format!("expected '{}' header", HEADER)));
+        }
+
+        let mut fields = HashMap::new();
+        for line in lines {
+            let line = line?;
+            let (key, value) = line.split_once(' ').unwrap_or((&line, ""));
+            fields.insert(key.to_string(), value.to_string());
+        }
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .map(|v| v.as_str())
+                .ok_or_else(|| invalid(format!("missing '{}'", key)))
+        };
+
+        let opcode = match field("opcode")? {
+            "???" => Opcode::Uninitialized,
+            m => opcodes
+                .from_mnemonic(m)
+                .ok_or_else(|| invalid(format!("bad opcode '{}'", m)))?,
+        };
+        let state = match field("state")? {
+            "WaitingToRun" => State::WaitingToRun,
+            "BlockedOnInput" => State::BlockedOnInput,
+            "BlockedOnOutput" => State::BlockedOnOutput,
+            "Terminated" => State::Terminated,
+            "BudgetExhausted" => State::BudgetExhausted,
+            "WatchpointHit" => State::WatchpointHit,
+            s => return Err(invalid(format!("bad state '{}'", s))),
+        };
+        let memory_backend = match fields.get("memory_backend").map(|v| v.as_str()) {
+            None | Some("Dense") => MemoryBackend::Dense,
+            Some("Paged") => MemoryBackend::Paged,
+            Some(b) => return Err(invalid(format!("bad memory backend '{}'", b))),
+        };
+        let mut memory = vec![(0, split(field("memory")?)?)];
+        for (key, value) in &fields {
+            if let Some(start) = key.strip_prefix("memory@") {
+                memory.push((parse(start)?, split(value)?));
+            }
+        }
+        memory.sort_by_key(|(start, _)| *start);
+        let pmodes = split::<i64>(field("pmodes")?)?
+            .into_iter()
+            .map(|m| ParameterMode::try_from(m).map_err(invalid))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Snapshot {
+            memory,
+            memory_backend,
+            instr: parse(field("ip")?)?,
+            relative_base: parse(field("relative_base")?)?,
+            steps: fields.get("steps").map_or(Ok(0), |v| parse(v))?,
+            opcode,
+            pmodes,
+            state,
+            input_queue: split(fields.get("input_queue").map_or("", |v| v.as_str()))?,
+            output_buffer: split(fields.get("output_buffer").map_or("", |v| v.as_str()))?,
+        })
+    }
+}
+
+fn join<T: ToString>(it: impl Iterator<Item = T>) -> String {
+    it.map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn split<T: FromStr>(s: &str) -> io::Result<Vec<T>> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    s.split(',').map(parse).collect()
+}
@@ -0,0 +1,470 @@
+//! Symbolic execution of Intcode programs whose control flow doesn't depend on their inputs, like
+//! day 2's, where the noun and verb are written into memory before the program runs.
+
+use super::{IntcodeComputer, Opcode, ParameterMode, State, budget::Budget};
+
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive};
+
+/// Programs that run longer than this are assumed not to finish and solved by brute force.
+const MAX_STEPS: u64 = 1_000_000;
+/// Brute force gives up after running the program this many times.
+const MAX_RUNS: usize = 1_000_000;
+
+/// Variables in a monomial, by the address they were read from, with their exponents.
+type Monomial = Vec<(usize, u32)>;
+
+/// A polynomial with integer coefficients over memory cells, printed like `100*[1] + [2] + 7`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Polynomial {
+    terms: BTreeMap<Monomial, i64>,
+}
+
+impl Polynomial {
+    pub fn constant(c: i64) -> Self {
+        let mut p = Polynomial::default();
+        if c != 0 {
+            p.terms.insert(Vec::new(), c);
+        }
+        p
+    }
+
+    /// The value of the cell at `addr`.
+    pub fn var(addr: usize) -> Self {
+        Polynomial {
+            terms: BTreeMap::from([(vec![(addr, 1)], 1)]),
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&Vec::new()).copied(),
+            _ => None,
+        }
+    }
+
+    /// The highest power of the variable at `addr`.
+    pub fn degree_in(&self, addr: usize) -> u32 {
+        self.terms
+            .keys()
+            .flat_map(|m| m.iter().filter(|(a, _)| *a == addr).map(|(_, e)| *e))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// `None` if the result doesn't fit in an `i64`.
+    pub fn checked_add(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut sum = self.clone();
+        for (m, c) in &other.terms {
+            sum.add_term(m.clone(), *c)?;
+        }
+        Some(sum)
+    }
+
+    /// `None` if the result doesn't fit in an `i64`.
+    pub fn checked_mul(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut product = Polynomial::default();
+        for (m1, c1) in &self.terms {
+            for (m2, c2) in &other.terms {
+                let mut vars: BTreeMap<usize, u32> = m1.iter().copied().collect();
+                for (addr, e) in m2 {
+                    *vars.entry(*addr).or_default() += e;
+                }
+                product.add_term(vars.into_iter().collect(), c1.checked_mul(*c2)?)?;
+            }
+        }
+        Some(product)
+    }
+
+    /// Replaces the variable at `addr` with `value`.
+    pub fn substitute(&self, addr: usize, value: i64) -> Option<Polynomial> {
+        let mut result = Polynomial::default();
+        for (m, c) in &self.terms {
+            let mut coefficient = *c;
+            let mut rest = Vec::new();
+            for &(a, e) in m {
+                if a == addr {
+                    coefficient = coefficient.checked_mul(value.checked_pow(e)?)?;
+                } else {
+                    rest.push((a, e));
+                }
+            }
+            result.add_term(rest, coefficient)?;
+        }
+        Some(result)
+    }
+
+    /// The value with every variable given by `value(addr)`.
+    pub fn evaluate(&self, value: impl Fn(usize) -> i64) -> Option<i64> {
+        self.terms.iter().try_fold(0i64, |sum, (m, c)| {
+            let term = m.iter().try_fold(*c, |t, &(addr, e)| {
+                t.checked_mul(value(addr).checked_pow(e)?)
+            })?;
+            sum.checked_add(term)
+        })
+    }
+
+    fn add_term(&mut self, monomial: Monomial, c: i64) -> Option<()> {
+        let entry = self.terms.entry(monomial).or_insert(0);
+        *entry = entry.checked_add(c)?;
+        self.terms.retain(|_, c| *c != 0);
+        Some(())
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        // Highest degree first, so the constant comes last.
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        terms.sort_by_key(|(m, _)| std::cmp::Reverse(m.iter().map(|(_, e)| e).sum::<u32>()));
+        for (i, (m, c)) in terms.into_iter().enumerate() {
+            let c = match (i, *c < 0) {
+                (0, true) => {
+                    write!(f, "-")?;
+                    -(*c as i128)
+                }
+                (0, false) => *c as i128,
+                (_, true) => {
+                    write!(f, " - ")?;
+                    -(*c as i128)
+                }
+                (_, false) => {
+                    write!(f, " + ")?;
+                    *c as i128
+                }
+            };
+            let vars: Vec<String> = m
+                .iter()
+                .map(|(addr, e)| match e {
+                    1 => format!("[{}]", addr),
+                    e => format!("[{}]^{}", addr, e),
+                })
+                .collect();
+            match (c, vars.is_empty()) {
+                (_, true) => write!(f, "{}", c)?,
+                (1, false) => write!(f, "{}", vars.join("*"))?,
+                (c, false) => write!(f, "{}*{}", c, vars.join("*"))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Known(Polynomial),
+    /// Read through an address that depends on the variables.
+    Unknown,
+}
+
+/// Runs `program` with the cells at `vars` treated as variables and returns what ends up in
+/// memory at `output` when it halts. Returns `None` if that can't be worked out: the program
+/// branches on, jumps to, writes through or compares a variable, does I/O, fails, or doesn't halt
+/// within `MAX_STEPS`.
+///
+/// Reads through an address that depends on a variable are allowed as long as what they read is
+/// overwritten before it matters, as day 2's first instruction is.
+pub fn evaluate(program: &[i64], vars: &[usize], output: usize) -> Option<Polynomial> {
+    let mut memory: Vec<Value> = program
+        .iter()
+        .map(|i| Value::Known(Polynomial::constant(*i)))
+        .collect();
+    for &addr in vars {
+        if addr >= memory.len() {
+            memory.resize(addr + 1, Value::Known(Polynomial::default()));
+        }
+        memory[addr] = Value::Known(Polynomial::var(addr));
+    }
+
+    let mut ip = 0;
+    let mut relative_base = 0;
+    for _ in 0..MAX_STEPS {
+        let word = constant(read(&memory, ip))?;
+        let opcode = Opcode::try_from(word % 100).ok()?;
+        let params: Vec<(ParameterMode, Value)> = (0..opcode.num_params())
+            .map(|i| {
+                let mode = ParameterMode::try_from(word / 10i64.pow(i as u32 + 2) % 10).ok()?;
+                Some((mode, read(&memory, ip + i + 1)))
+            })
+            .collect::<Option<_>>()?;
+
+        // The value of a source parameter.
+        let src = |i: usize| -> Value {
+            let (mode, param) = &params[i];
+            match mode {
+                ParameterMode::Immediate => param.clone(),
+                _ => match address(*mode, param, relative_base) {
+                    Some(addr) => read(&memory, addr),
+                    None => Value::Unknown,
+                },
+            }
+        };
+        let dst = |i: usize| -> Option<usize> {
+            let (mode, param) = &params[i];
+            match mode {
+                ParameterMode::Immediate => None,
+                _ => address(*mode, param, relative_base),
+            }
+        };
+
+        match opcode {
+            Opcode::Add | Opcode::Multiply => {
+                let value = match (src(0), src(1)) {
+                    (Value::Known(a), Value::Known(b)) => Value::Known(match opcode {
+                        Opcode::Add => a.checked_add(&b)?,
+                        _ => a.checked_mul(&b)?,
+                    }),
+                    _ => Value::Unknown,
+                };
+                let dst = dst(2)?;
+                write(&mut memory, dst, value);
+            }
+            Opcode::LessThan | Opcode::Equals => {
+                let (a, b) = (constant(src(0))?, constant(src(1))?);
+                let result = match opcode {
+                    Opcode::LessThan => a < b,
+                    _ => a == b,
+                };
+                let dst = dst(2)?;
+                write(
+                    &mut memory,
+                    dst,
+                    Value::Known(Polynomial::constant(result as i64)),
+                );
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let jump = (constant(src(0))? != 0) == (opcode == Opcode::JumpIfTrue);
+                if jump {
+                    ip = usize::try_from(constant(src(1))?).ok()?;
+                    continue;
+                }
+            }
+            Opcode::RelativeBaseOffset => {
+                let offset = constant(src(0))?;
+                relative_base = relative_base.checked_add(offset)?;
+            }
+            Opcode::Terminate => {
+                return match read(&memory, output) {
+                    Value::Known(p) => Some(p),
+                    Value::Unknown => None,
+                };
+            }
+            Opcode::Input | Opcode::Output | Opcode::Custom(_) | Opcode::Uninitialized => {
+                return None;
+            }
+        }
+        ip += opcode.num_params() + 1;
+    }
+    None
+}
+
+fn read(memory: &[Value], addr: usize) -> Value {
+    memory
+        .get(addr)
+        .cloned()
+        .unwrap_or(Value::Known(Polynomial::default()))
+}
+
+fn write(memory: &mut Vec<Value>, addr: usize, value: Value) {
+    if addr >= memory.len() {
+        memory.resize(addr + 1, Value::Known(Polynomial::default()));
+    }
+    memory[addr] = value;
+}
+
+fn constant(value: Value) -> Option<i64> {
+    match value {
+        Value::Known(p) => p.as_constant(),
+        Value::Unknown => None,
+    }
+}
+
+/// The address a position or relative parameter refers to, if it doesn't depend on a variable.
+fn address(mode: ParameterMode, param: &Value, relative_base: i64) -> Option<usize> {
+    let offset = match mode {
+        ParameterMode::Relative => relative_base,
+        _ => 0,
+    };
+    usize::try_from(constant(param.clone())?.checked_add(offset)?).ok()
+}
+
+/// An assignment of values to the variables that makes the program output a target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub target: i64,
+    /// In the order the variables were added.
+    pub values: Vec<i64>,
+}
+
+/// Finds the values of some memory cells that make a program leave one of a set of targets in
+/// another, e.g. day 2's noun and verb:
+///
+/// ```text
+/// let solutions = Solver::new(program, 0)
+///     .var(1, 0..=99)
+///     .var(2, 0..=99)
+///     .solve(&[19690720]);
+/// ```
+///
+/// The output is worked out as a polynomial once with `evaluate` and solved for, falling back to
+/// running the program for every combination of values (up to a limit) if that's not possible.
+#[derive(Clone, Debug)]
+pub struct Solver {
+    program: Vec<i64>,
+    output: usize,
+    vars: Vec<(usize, RangeInclusive<i64>)>,
+}
+
+impl Solver {
+    pub fn new(program: Vec<i64>, output: usize) -> Self {
+        Solver {
+            program,
+            output,
+            vars: Vec::new(),
+        }
+    }
+
+    /// Adds a variable at `addr`, to be tried with values in `range`.
+    pub fn var(mut self, addr: usize, range: RangeInclusive<i64>) -> Self {
+        self.vars.push((addr, range));
+        self
+    }
+
+    /// The output as a polynomial in the variables, if it can be worked out symbolically.
+    pub fn polynomial(&self) -> Option<Polynomial> {
+        let addrs: Vec<usize> = self.vars.iter().map(|(a, _)| *a).collect();
+        evaluate(&self.program, &addrs, self.output)
+    }
+
+    /// Every solution for every target, ordered by the values of the variables.
+    pub fn solve(&self, targets: &[i64]) -> Vec<Solution> {
+        let mut solutions = match self.polynomial() {
+            Some(p) => self.solve_polynomial(&p, targets),
+            None => self.brute_force(targets),
+        };
+        solutions.sort_by(|a, b| a.values.cmp(&b.values).then(a.target.cmp(&b.target)));
+        solutions
+    }
+
+    fn solve_polynomial(&self, p: &Polynomial, targets: &[i64]) -> Vec<Solution> {
+        let mut solutions = Vec::new();
+        let Some(((last, last_range), rest)) = self.vars.split_last() else {
+            if let Some(c) = p.as_constant() {
+                solutions.extend(targets.iter().filter(|t| **t == c).map(|t| Solution {
+                    target: *t,
+                    values: Vec::new(),
+                }));
+            }
+            return solutions;
+        };
+
+        // Fix all but the last variable, then solve for that one.
+        for prefix in combinations(rest) {
+            let Some(q) = rest
+                .iter()
+                .zip(&prefix)
+                .try_fold(p.clone(), |q, ((addr, _), v)| q.substitute(*addr, *v))
+            else {
+                continue;
+            };
+            for &target in targets {
+                for v in roots(&q, *last, last_range.clone(), target) {
+                    let mut values = prefix.clone();
+                    values.push(v);
+                    solutions.push(Solution { target, values });
+                }
+            }
+        }
+        solutions
+    }
+
+    /// Runs the program for every combination of values, up to `MAX_RUNS` of them. Only runs
+    /// that halt within `MAX_STEPS` count; ones that fail, wait for I/O or don't halt in time
+    /// are skipped.
+    fn brute_force(&self, targets: &[i64]) -> Vec<Solution> {
+        let mut solutions = Vec::new();
+        for values in combinations(&self.vars).take(MAX_RUNS) {
+            let mut c = IntcodeComputer::new(self.program.clone()).with_budget(Budget {
+                max_steps: Some(MAX_STEPS),
+                ..Budget::default()
+            });
+            for ((addr, _), v) in self.vars.iter().zip(&values) {
+                c.write_mem(*addr, *v);
+            }
+            if c.run().is_err() || c.get_state() != State::Terminated {
+                continue;
+            }
+            let result = c.read_mem(self.output);
+            solutions.extend(targets.iter().filter(|t| **t == result).map(|t| Solution {
+                target: *t,
+                values: values.clone(),
+            }));
+        }
+        solutions
+    }
+}
+
+/// Every combination of values for `vars`, in order: an odometer with the last variable turning
+/// fastest.
+fn combinations(vars: &[(usize, RangeInclusive<i64>)]) -> Combinations<'_> {
+    let next = vars
+        .iter()
+        .map(|(_, range)| (!range.is_empty()).then(|| *range.start()))
+        .collect();
+    Combinations { vars, next }
+}
+
+struct Combinations<'a> {
+    vars: &'a [(usize, RangeInclusive<i64>)],
+    /// `None` once every combination has been given out.
+    next: Option<Vec<i64>>,
+}
+
+impl Iterator for Combinations<'_> {
+    type Item = Vec<i64>;
+
+    fn next(&mut self) -> Option<Vec<i64>> {
+        let current = self.next.take()?;
+        let mut next = current.clone();
+        for (v, (_, range)) in next.iter_mut().zip(self.vars).rev() {
+            if *v < *range.end() {
+                *v += 1;
+                self.next = Some(next);
+                break;
+            }
+            *v = *range.start();
+        }
+        Some(current)
+    }
+}
+
+/// The values of the variable at `addr` in `range` for which `p`, a polynomial in just that
+/// variable, equals `target`.
+fn roots(p: &Polynomial, addr: usize, range: RangeInclusive<i64>, target: i64) -> Vec<i64> {
+    match p.degree_in(addr) {
+        0 if p.as_constant() == Some(target) => range.collect(),
+        0 => Vec::new(),
+        1 => {
+            // a*x + b = target
+            let b = p.substitute(addr, 0).and_then(|b| b.as_constant());
+            let a = p.substitute(addr, 1).and_then(|a1| a1.as_constant());
+            match (a, b) {
+                (Some(a1), Some(b)) => a1
+                    .checked_sub(b)
+                    .zip(target.checked_sub(b))
+                    .filter(|(a, rhs)| rhs.checked_rem(*a) == Some(0))
+                    .map(|(a, rhs)| rhs / a)
+                    .filter(|x| range.contains(x))
+                    .into_iter()
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+        _ => range
+            .filter(|v| p.evaluate(|_| *v) == Some(target))
+            .collect(),
+    }
+}
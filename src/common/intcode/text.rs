@@ -0,0 +1,11 @@
+//! Parsing helpers shared by the plain-text formats snapshots and recordings are saved in.
+
+use std::{io, str::FromStr};
+
+pub(super) fn parse<T: FromStr>(s: &str) -> io::Result<T> {
+    s.parse().map_err(|_| invalid(format!("bad value '{}'", s)))
+}
+
+pub(super) fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
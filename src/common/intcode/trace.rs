@@ -0,0 +1,142 @@
+use super::{Opcode, disasm::Operand};
+
+use std::io::{self, Write};
+
+/// Everything one executed instruction did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    pub instr: usize,
+    pub opcode: Opcode,
+    pub operands: Vec<Operand>,
+    /// The resolved values of the instruction's source operands, in order.
+    pub values: Vec<i64>,
+    /// `(address, value)` written to memory.
+    pub write: Option<(usize, i64)>,
+    /// `(old, new)` relative base.
+    pub relative_base: Option<(i64, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+    /// Target of a jump that was taken.
+    pub jump: Option<usize>,
+}
+
+pub trait TraceSink {
+    fn trace(&mut self, event: &TraceEvent) -> io::Result<()>;
+
+    /// Sinks that return false here are never attached, so the computer doesn't even build
+    /// events for them.
+    fn enabled(&self) -> bool {
+        true
+    }
+}
+
+pub struct NoTrace;
+
+impl TraceSink for NoTrace {
+    fn trace(&mut self, _event: &TraceEvent) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn enabled(&self) -> bool {
+        false
+    }
+}
+
+/// One human-readable line per instruction, e.g. `    12: $7 = 3 + 4 = 7`.
+pub struct TextTrace<W: Write>(pub W);
+
+impl<W: Write> TraceSink for TextTrace<W> {
+    fn trace(&mut self, e: &TraceEvent) -> io::Result<()> {
+        write!(self.0, "{:>6}: ", e.instr)?;
+        match (e.opcode, e.write) {
+            (Opcode::Add, Some((dst, result))) => writeln!(
+                self.0,
+                "${} = {} + {} = {}",
+                dst, e.values[0], e.values[1], result
+            ),
+            (Opcode::Multiply, Some((dst, result))) => writeln!(
+                self.0,
+                "${} = {} * {} = {}",
+                dst, e.values[0], e.values[1], result
+            ),
+            (Opcode::Input, Some((dst, input))) => {
+                writeln!(self.0, "${} = $input = {}", dst, input)
+            }
+            (Opcode::LessThan | Opcode::Equals, Some((dst, result))) => {
+                writeln!(self.0, "${} = {}", dst, result)
+            }
+            (Opcode::Output, _) => writeln!(self.0, "$output = {}", e.values[0]),
+            (Opcode::JumpIfTrue | Opcode::JumpIfFalse, _) => match e.jump {
+                Some(dst) => writeln!(self.0, "$ip = {}", dst),
+                None => writeln!(self.0, "no jump"),
+            },
+            (Opcode::RelativeBaseOffset, _) => writeln!(
+                self.0,
+                "$relative_base += ({}) = {}",
+                e.values[0],
+                e.relative_base.map_or(0, |(_, new)| new)
+            ),
+            (Opcode::Custom(c), Some((dst, result))) => {
+                writeln!(self.0, "{} ${} = {}", c.mnemonic, dst, result)
+            }
+            _ => writeln!(self.0, "{}", e.opcode.mnemonic()),
+        }
+    }
+}
+
+/// One JSON object per instruction. Optional fields are only present when the instruction had
+/// that effect, e.g.
+/// `{"ip":12,"op":"ADD","operands":["[1]","#4","rb+2"],"values":[3,4],"write":[7,7]}`.
+pub struct JsonTrace<W: Write>(pub W);
+
+impl<W: Write> TraceSink for JsonTrace<W> {
+    fn trace(&mut self, e: &TraceEvent) -> io::Result<()> {
+        let operands: Vec<String> = e
+            .operands
+            .iter()
+            .map(|o| format!("\"{}\"", json_escape(&o.to_string())))
+            .collect();
+        let values: Vec<String> = e.values.iter().map(|v| v.to_string()).collect();
+        write!(
+            self.0,
+            "{{\"ip\":{},\"op\":\"{}\",\"operands\":[{}],\"values\":[{}]",
+            e.instr,
+            json_escape(e.opcode.mnemonic()),
+            operands.join(","),
+            values.join(",")
+        )?;
+        if let Some((addr, value)) = e.write {
+            write!(self.0, ",\"write\":[{},{}]", addr, value)?;
+        }
+        if let Some((old, new)) = e.relative_base {
+            write!(self.0, ",\"relative_base\":[{},{}]", old, new)?;
+        }
+        if let Some(i) = e.input {
+            write!(self.0, ",\"input\":{}", i)?;
+        }
+        if let Some(o) = e.output {
+            write!(self.0, ",\"output\":{}", o)?;
+        }
+        if let Some(dst) = e.jump {
+            write!(self.0, ",\"jump\":{}", dst)?;
+        }
+        writeln!(self.0, "}}")
+    }
+}
+
+/// `s` with everything JSON doesn't allow in a string escaped, ready to go between quotes.
+pub fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c < ' ' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
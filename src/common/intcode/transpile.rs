@@ -0,0 +1,367 @@
+//! Ahead-of-time translation of Intcode programs into Rust source.
+//!
+//! The generated module has a single entry point:
+//!
+//! ```text
+//! pub fn run<'a>(
+//!     memory: Vec<i64>,
+//!     input: impl InputSource + 'a,
+//!     output: impl OutputSink + 'a,
+//! ) -> Result<IntcodeComputer<'a>, IntcodeError>
+//! ```
+//!
+//! It runs as a `match` on the instruction pointer, one arm per basic block of the program's
+//! control-flow graph, with operands and modes resolved at translation time. Anything it can't do
+//! itself is handed to the interpreter by `resume`, before the instruction concerned has had any
+//! effect:
+//!
+//! - writes to a cell that holds code, and `memory` whose code differs from the translated program
+//! - jumps to an address that isn't the start of a known block
+//! - negative addresses, invalid instructions and `HLT`
+//! - arithmetic or relative addresses that overflow, which the interpreter reports as errors
+//! - an input source with nothing to give (which the interpreter then asks again), and output
+//!   errors (retried by the interpreter)
+//!
+//! So `run` returns the interpreter in whatever state it stopped in, e.g. `Terminated`, or
+//! `BlockedOnInput` to be driven further by hand. Its step count only covers the instructions it
+//! ran itself.
+//!
+//! The module refers to the interpreter as `aoc19::common::intcode`.
+
+use super::{
+    IntcodeComputer, IntcodeError, Opcode, ParameterMode,
+    cfg::Cfg,
+    disasm::{Line, Operand},
+    ports::{InputSource, OutputSink},
+};
+
+use std::{collections::BTreeSet, fmt::Write};
+
+/// Carries on running a transpiled program in the interpreter from `instr`.
+pub fn resume<'a>(
+    memory: Vec<i64>,
+    instr: usize,
+    relative_base: i64,
+    input: impl InputSource + 'a,
+    output: impl OutputSink + 'a,
+) -> Result<IntcodeComputer<'a>, IntcodeError> {
+    let mut c = IntcodeComputer::new(memory)
+        .with_input(input)
+        .with_output(output);
+    c.set_instr(instr);
+    c.set_relative_base(relative_base);
+    c.run()?;
+    Ok(c)
+}
+
+pub fn transpile(program: &[i64]) -> String {
+    let cfg = Cfg::build(program);
+    let mut code = BTreeSet::new();
+    for line in cfg.blocks.values().flat_map(|b| &b.lines) {
+        if let Line::Instruction { addr, .. } = line {
+            code.extend(*addr..addr + line.size());
+        }
+    }
+
+    let mut out = String::new();
+    let w = &mut out;
+    wl(
+        w,
+        0,
+        &format!(
+            "//! Transpiled from a {}-word Intcode program.",
+            program.len()
+        ),
+    );
+    wl(w, 0, "");
+    wl(w, 0, "#![allow(dead_code)]");
+    wl(w, 0, "");
+    wl(w, 0, "use aoc19::common::intcode::{");
+    wl(w, 1, "IntcodeComputer, IntcodeError,");
+    wl(w, 1, "ports::{InputSource, OutputSink},");
+    wl(w, 1, "transpile::resume,");
+    wl(w, 0, "};");
+    wl(w, 0, "");
+    wl(
+        w,
+        0,
+        &format!("const PROGRAM: [i64; {}] = [", program.len()),
+    );
+    for chunk in program.chunks(16) {
+        let words: Vec<_> = chunk.iter().map(|i| i.to_string()).collect();
+        wl(w, 1, &format!("{},", words.join(", ")));
+    }
+    wl(w, 0, "];");
+    wl(w, 0, "");
+    wl(
+        w,
+        0,
+        "/// `[start, end)` runs of the cells holding translated instructions.",
+    );
+    wl(w, 0, "const CODE: &[(usize, usize)] = &[");
+    for (start, end) in runs(&code) {
+        wl(w, 1, &format!("({}, {}),", start, end));
+    }
+    wl(w, 0, "];");
+    wl(w, 0, "");
+    w.push_str(HELPERS);
+    wl(w, 0, "");
+    wl(w, 0, "#[allow(unused_mut, unreachable_code, clippy::all)]");
+    wl(w, 0, "pub fn run<'a>(");
+    wl(w, 1, "mut mem: Vec<i64>,");
+    wl(w, 1, "mut input: impl InputSource + 'a,");
+    wl(w, 1, "mut output: impl OutputSink + 'a,");
+    wl(w, 0, ") -> Result<IntcodeComputer<'a>, IntcodeError> {");
+    wl(w, 1, "let mut ip = 0usize;");
+    wl(w, 1, "let mut rb = 0i64;");
+    wl(w, 1, "if !intact(&mem) {");
+    wl(w, 2, "return resume(mem, ip, rb, input, output);");
+    wl(w, 1, "}");
+    wl(w, 1, "'run: loop {");
+    wl(w, 2, "match ip {");
+    for block in cfg.blocks.values() {
+        wl(w, 3, &format!("{} => {{", block.start));
+        let mut next = Some(block.start);
+        for line in &block.lines {
+            next = instruction(w, &code, line);
+            if next.is_none() {
+                break;
+            }
+        }
+        if let Some(next) = next {
+            wl(w, 4, &format!("ip = {};", next));
+        }
+        wl(w, 3, "}");
+    }
+    wl(w, 3, "_ => break 'run,");
+    wl(w, 2, "}");
+    wl(w, 1, "}");
+    wl(w, 1, "resume(mem, ip, rb, input, output)");
+    wl(w, 0, "}");
+    out
+}
+
+const HELPERS: &str = "\
+fn load(mem: &[i64], addr: usize) -> i64 {
+    mem.get(addr).copied().unwrap_or(0)
+}
+
+fn store(mem: &mut Vec<i64>, addr: usize, value: i64) {
+    if addr >= mem.len() {
+        mem.resize(addr + 1, 0);
+    }
+    mem[addr] = value;
+}
+
+fn addr(a: i64) -> Option<usize> {
+    usize::try_from(a).ok()
+}
+
+fn is_code(addr: usize) -> bool {
+    let i = CODE.partition_point(|&(_, end)| end <= addr);
+    CODE.get(i).is_some_and(|&(start, _)| start <= addr)
+}
+
+fn intact(mem: &[i64]) -> bool {
+    CODE.iter()
+        .all(|&(start, end)| mem.get(start..end) == Some(&PROGRAM[start..end]))
+}
+";
+
+/// Writes one indented line.
+fn wl(w: &mut String, depth: usize, s: &str) {
+    if s.is_empty() {
+        w.push('\n');
+    } else {
+        writeln!(w, "{}{}", "    ".repeat(depth), s).expect("writing to a String");
+    }
+}
+
+/// Contiguous `[start, end)` runs of `cells`.
+fn runs(cells: &BTreeSet<usize>) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &c in cells {
+        match runs.last_mut() {
+            Some((_, end)) if *end == c => *end += 1,
+            _ => runs.push((c, c + 1)),
+        }
+    }
+    runs
+}
+
+/// The Rust for one instruction, as a list of statements.
+struct Emitted {
+    stmts: Vec<String>,
+    handoff: String,
+}
+
+impl Emitted {
+    fn new(addr: usize) -> Self {
+        Emitted {
+            stmts: Vec::new(),
+            handoff: format!("ip = {}; break 'run;", addr),
+        }
+    }
+
+    /// An `rb`-relative address, handing off if it's negative or overflows.
+    fn rel_addr(&mut self, name: String, offset: i64) -> String {
+        let a = match offset {
+            0 => "addr(rb)".to_string(),
+            o => format!("rb.checked_add({}).and_then(addr)", o),
+        };
+        self.stmts.push(format!(
+            "let Some({}) = {} else {{ {} }};",
+            name, a, self.handoff
+        ));
+        name
+    }
+
+    /// The value of a source operand, or `None` if the instruction always hands off.
+    fn src(&mut self, addr: usize, i: usize, operand: Operand) -> Option<String> {
+        match operand.mode {
+            ParameterMode::Immediate => Some(operand.value.to_string()),
+            ParameterMode::Position if operand.value < 0 => None,
+            ParameterMode::Position => Some(format!("load(&mem, {})", operand.value)),
+            ParameterMode::Relative => {
+                let a = self.rel_addr(format!("a{}_{}", addr, i), operand.value);
+                Some(format!("load(&mem, {})", a))
+            }
+        }
+    }
+
+    /// The address a destination operand writes to, or `None` if the instruction always hands
+    /// off.
+    fn dst(&mut self, code: &BTreeSet<usize>, addr: usize, operand: Operand) -> Option<String> {
+        match operand.mode {
+            ParameterMode::Immediate => None,
+            ParameterMode::Position => usize::try_from(operand.value)
+                .ok()
+                .filter(|a| !code.contains(a))
+                .map(|a| a.to_string()),
+            ParameterMode::Relative => {
+                let a = self.rel_addr(format!("d{}", addr), operand.value);
+                self.stmts
+                    .push(format!("if is_code({}) {{ {} }}", a, self.handoff));
+                Some(a)
+            }
+        }
+    }
+}
+
+/// Writes the statements for `line` and returns the address execution carries on at, or `None`
+/// if control never gets past it.
+fn instruction(w: &mut String, code: &BTreeSet<usize>, line: &Line) -> Option<usize> {
+    wl(w, 4, &format!("// {}", line));
+    let Line::Instruction {
+        addr,
+        opcode,
+        operands,
+    } = line
+    else {
+        wl(w, 4, &format!("ip = {}; break 'run;", line.addr()));
+        return None;
+    };
+    let (addr, next) = (*addr, line.addr() + line.size());
+
+    let mut e = Emitted::new(addr);
+    match emit(&mut e, code, addr, *opcode, operands) {
+        Some(falls_through) => {
+            for s in &e.stmts {
+                wl(w, 4, s);
+            }
+            falls_through.then_some(next)
+        }
+        None => {
+            wl(w, 4, &e.handoff);
+            None
+        }
+    }
+}
+
+/// Fills in `e` for one instruction. Returns whether execution can carry on to the next
+/// instruction, or `None` if the instruction always hands off to the interpreter.
+fn emit(
+    e: &mut Emitted,
+    code: &BTreeSet<usize>,
+    addr: usize,
+    opcode: Opcode,
+    operands: &[Operand],
+) -> Option<bool> {
+    match opcode {
+        Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+            let a = e.src(addr, 0, operands[0])?;
+            let b = e.src(addr, 1, operands[1])?;
+            let d = e.dst(code, addr, operands[2])?;
+            let stmt = match opcode {
+                Opcode::Add | Opcode::Multiply => format!(
+                    "let Some(v{}) = i64::checked_{}({}, {}) else {{ {} }};",
+                    addr,
+                    if opcode == Opcode::Add { "add" } else { "mul" },
+                    a,
+                    b,
+                    e.handoff
+                ),
+                Opcode::LessThan => format!("let v{} = ({} < {}) as i64;", addr, a, b),
+                _ => format!("let v{} = ({} == {}) as i64;", addr, a, b),
+            };
+            e.stmts.push(stmt);
+            e.stmts.push(format!("store(&mut mem, {}, v{});", d, addr));
+        }
+        Opcode::Input => {
+            let d = e.dst(code, addr, operands[0])?;
+            e.stmts.push(format!(
+                "let Some(v{}) = input.next_input() else {{ {} }};",
+                addr, e.handoff
+            ));
+            e.stmts.push(format!("store(&mut mem, {}, v{});", d, addr));
+        }
+        Opcode::Output => {
+            let v = e.src(addr, 0, operands[0])?;
+            e.stmts.push(format!(
+                "if output.output({}).is_err() {{ {} }}",
+                v, e.handoff
+            ));
+        }
+        Opcode::RelativeBaseOffset => {
+            let v = e.src(addr, 0, operands[0])?;
+            e.stmts.push(format!(
+                "let Some(r{}) = rb.checked_add({}) else {{ {} }};",
+                addr, v, e.handoff
+            ));
+            e.stmts.push(format!("rb = r{};", addr));
+        }
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            // Both operands are read whether or not the jump is taken.
+            let cond = e.src(addr, 0, operands[0])?;
+            let target = e.src(addr, 1, operands[1])?;
+            let go = match operands[1] {
+                Operand {
+                    mode: ParameterMode::Immediate,
+                    value,
+                } => match usize::try_from(value) {
+                    Ok(t) => format!("ip = {}; continue 'run;", t),
+                    Err(_) => e.handoff.clone(),
+                },
+                _ => format!(
+                    "let Some(t) = addr({}) else {{ {} }}; ip = t; continue 'run;",
+                    target, e.handoff
+                ),
+            };
+            if operands[0].mode == ParameterMode::Immediate {
+                let taken = (operands[0].value != 0) == (opcode == Opcode::JumpIfTrue);
+                if taken {
+                    e.stmts.push(go);
+                }
+                return Some(!taken);
+            }
+            let test = if opcode == Opcode::JumpIfTrue {
+                "!="
+            } else {
+                "=="
+            };
+            e.stmts.push(format!("if {} {} 0 {{ {} }}", cond, test, go));
+        }
+        Opcode::Terminate | Opcode::Custom(_) | Opcode::Uninitialized => return None,
+    }
+    Some(true)
+}
@@ -1,6 +1,6 @@
-use crate::common::intcode::IntcodeComputer;
+use crate::common::intcode::{IntcodeComputer, symbolic::Solver, trace::TextTrace};
 
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 
 use aoclib_rs::{prep_io, printwriteln, split_and_parse};
 
@@ -16,21 +16,21 @@ pub fn run() {
 fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
     let mut c = IntcodeComputer::new(memory.clone());
     c.set_day2_input(12, 2);
-    c.run(true /* verbose */);
+    c.set_trace(TextTrace(io::stdout()));
+    c.run().unwrap();
     printwriteln!(writer, "{}", c.get_day2_output()).unwrap();
 }
 
 fn part2<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
-    for noun in 0..=99 {
-        for verb in 0..=99 {
-            let mut c = IntcodeComputer::new(memory.clone());
-            c.set_day2_input(noun, verb);
-            c.run(false /* verbose */);
-
-            if c.get_day2_output() == 19690720 {
-                printwriteln!(writer, "100 * {} + {} = {}", noun, verb, 100 * noun + verb).unwrap();
-                return;
-            }
+    let solutions = Solver::new(memory, 0)
+        .var(1, 0..=99)
+        .var(2, 0..=99)
+        .solve(&[19690720]);
+    match solutions.first() {
+        Some(solution) => {
+            let (noun, verb) = (solution.values[0], solution.values[1]);
+            printwriteln!(writer, "100 * {} + {} = {}", noun, verb, 100 * noun + verb).unwrap();
         }
+        None => eprintln!("error: no noun and verb give 19690720"),
     }
 }
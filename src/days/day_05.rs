@@ -1,6 +1,6 @@
-use crate::common::intcode::IntcodeComputer;
+use crate::common::intcode::{IntcodeComputer, trace::TextTrace};
 
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 
 use aoclib_rs::{prep_io, printwriteln, split_and_parse};
 
@@ -15,8 +15,12 @@ pub fn run() {
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
     let mut output = Vec::new();
-    let mut c = IntcodeComputer::new_with_io(memory, || 1, |i| output.push(i));
-    c.run(true /* verbose */);
+    IntcodeComputer::new(memory)
+        .with_input(|| 1)
+        .with_output(|i| output.push(i))
+        .with_trace(TextTrace(io::stdout()))
+        .run()
+        .unwrap();
 
     for o in &output[..(output.len() - 1)] {
         if *o != 0 {
@@ -29,8 +33,12 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
 
 fn part2<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
     let mut output = Vec::new();
-    let mut c = IntcodeComputer::new_with_io(memory, || 5, |i| output.push(i));
-    c.run(true /* verbose */);
+    IntcodeComputer::new(memory)
+        .with_input(|| 5)
+        .with_output(|i| output.push(i))
+        .with_trace(TextTrace(io::stdout()))
+        .run()
+        .unwrap();
 
     if output.len() != 1 {
         panic!("got {} outputs, expected 1", output.len());
@@ -1,4 +1,4 @@
-use crate::common::intcode::{IntcodeComputer, State};
+use crate::common::intcode::{IntcodeComputer, network::Network, ports::IterInput};
 
 use std::io::{BufWriter, Write};
 
@@ -24,7 +24,7 @@ fn get_max(
     memory: Vec<i64>,
     perm: &mut Vec<i64>,
     rest: &mut Vec<i64>,
-    try_phase: fn(Vec<i64>, &Vec<i64>) -> i64,
+    try_phase: fn(Vec<i64>, &[i64]) -> i64,
 ) -> i64 {
     if rest.is_empty() {
         let t = try_phase(memory.clone(), perm);
@@ -44,17 +44,15 @@ fn get_max(
     max.0.expect("no loop iterations - impossible")
 }
 
-fn try_phase_part1(memory: Vec<i64>, phase: &Vec<i64>) -> i64 {
+fn try_phase_part1(memory: Vec<i64>, phase: &[i64]) -> i64 {
     let mut signal = 0;
     for p in phase {
         let input = [*p, signal];
-        let mut input_it = input.iter();
-        let mut c = IntcodeComputer::new_with_io(
-            memory.clone(),
-            || *input_it.next().unwrap(),
-            |i| signal = i,
-        );
-        c.run(false /* verbose */);
+        IntcodeComputer::new(memory.clone())
+            .with_input(IterInput(input.into_iter()))
+            .with_output(|i| signal = i)
+            .run()
+            .unwrap();
     }
     signal
 }
@@ -66,55 +64,17 @@ fn part2<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
     printwriteln!(writer, "{}", max).unwrap();
 }
 
-fn try_phase_part2(memory: Vec<i64>, phase: &Vec<i64>) -> i64 {
-    const VERBOSE: bool = false;
-
-    let mut computers = Vec::<IntcodeComputer<fn() -> i64, fn(i64)>>::new();
-    for _ in phase {
-        computers.push(IntcodeComputer::new(memory.clone()));
-    }
-
-    // first initialization - provide phase
-    for (i, computer) in computers.iter_mut().enumerate() {
-        computer.run(VERBOSE);
-        if computer.get_state() != State::BlockedOnInput {
-            panic!("unexpected state: {:?}", computer.get_state());
-        }
-        computer.provide_input(phase[i], VERBOSE);
+fn try_phase_part2(memory: Vec<i64>, phase: &[i64]) -> i64 {
+    let mut amplifiers = Network::ring(phase.iter().map(|_| memory.clone()));
+    for (i, p) in phase.iter().enumerate() {
+        amplifiers = amplifiers.input(i, [*p]);
     }
-
-    let mut i = 0;
-    let mut signal = 0;
-    loop {
-        match computers[i].get_state() {
-            State::WaitingToRun => {}
-            State::BlockedOnInput => {
-                computers[i].provide_input(signal, VERBOSE);
-            }
-            _ => panic!("unexpected state: {:?}", computers[i].get_state()),
-        }
-
-        computers[i].run(VERBOSE);
-
-        match computers[i].get_state() {
-            State::BlockedOnInput => {
-                i = (i + 1) % computers.len();
-                continue;
-            }
-            State::BlockedOnOutput => {
-                signal = computers[i].get_output(VERBOSE);
-                continue;
-            }
-            State::Terminated => {
-                if i == computers.len() - 1 {
-                    break;
-                }
-                i = (i + 1) % computers.len();
-                continue;
-            }
-            _ => panic!("unexpected state: {:?}", computers[i].get_state()),
-        }
+    let report = amplifiers.input(0, [0]).run();
+    if report.deadlocked {
+        panic!("amplifiers deadlocked");
     }
 
-    signal
+    report
+        .last_output(phase.len() - 1)
+        .expect("last amplifier never output")
 }
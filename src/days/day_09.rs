@@ -1,6 +1,6 @@
-use crate::common::intcode::IntcodeComputer;
+use crate::common::intcode::{IntcodeComputer, trace::TextTrace};
 
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 
 use aoclib_rs::{prep_io, printwriteln, split_and_parse};
 
@@ -15,8 +15,12 @@ pub fn run() {
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
     let mut output = Vec::new();
-    let mut c = IntcodeComputer::new_with_io(memory, || 1, |i| output.push(i));
-    c.run(true /* verbose */);
+    IntcodeComputer::new(memory)
+        .with_input(|| 1)
+        .with_output(|i| output.push(i))
+        .with_trace(TextTrace(io::stdout()))
+        .run()
+        .unwrap();
 
     if output.len() > 1 {
         for o in &output[..(output.len() - 1)] {
@@ -29,7 +33,10 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
 }
 
 fn part2<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
-    let mut c =
-        IntcodeComputer::new_with_io(memory, || 2, |i| printwriteln!(writer, "{}", i).unwrap());
-    c.run(true /* verbose */);
+    IntcodeComputer::new(memory)
+        .with_input(|| 2)
+        .with_output(|i| printwriteln!(writer, "{}", i).unwrap())
+        .with_trace(TextTrace(io::stdout()))
+        .run()
+        .unwrap();
 }
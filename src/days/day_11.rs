@@ -1,8 +1,12 @@
-use crate::common::{intcode, intcode::IntcodeComputer};
+use crate::common::intcode::{
+    IntcodeComputer, State,
+    peripheral::{Device, Peripheral},
+    trace::TextTrace,
+};
 
 use std::{
     collections::{HashMap, HashSet},
-    io::{BufWriter, Write},
+    io::{self, BufWriter, Write},
 };
 
 use aoclib_rs::{
@@ -17,12 +21,14 @@ enum Colour {
     White,
 }
 
-impl From<i64> for Colour {
-    fn from(i: i64) -> Colour {
+impl TryFrom<i64> for Colour {
+    type Error = String;
+
+    fn try_from(i: i64) -> Result<Colour, String> {
         match i {
-            0 => Colour::Black,
-            1 => Colour::White,
-            _ => panic!("bad colour"),
+            0 => Ok(Colour::Black),
+            1 => Ok(Colour::White),
+            _ => Err(format!("bad colour {}", i)),
         }
     }
 }
@@ -45,28 +51,45 @@ impl From<Colour> for char {
     }
 }
 
-#[derive(Copy, Clone)]
-enum State {
-    Paint,
-    Move,
-}
-
 #[derive(Copy, Clone)]
 enum Turn {
     Left,
     Right,
 }
 
-impl From<i64> for Turn {
-    fn from(i: i64) -> Turn {
+impl TryFrom<i64> for Turn {
+    type Error = String;
+
+    fn try_from(i: i64) -> Result<Turn, String> {
         match i {
-            0 => Turn::Left,
-            1 => Turn::Right,
-            _ => panic!("bad turn"),
+            0 => Ok(Turn::Left),
+            1 => Ok(Turn::Right),
+            _ => Err(format!("bad turn {}", i)),
         }
     }
 }
 
+enum RobotEvent {
+    Paint { colour: Colour },
+    Turn { dir: Turn },
+}
+
+/// The painting robot's output: `(colour, turn)` pairs.
+struct Robot;
+
+impl Device for Robot {
+    type Event = RobotEvent;
+
+    const FRAME_LEN: usize = 2;
+
+    fn decode(&mut self, frame: &[i64], events: &mut Vec<RobotEvent>) -> Result<(), String> {
+        let (colour, dir) = (Colour::try_from(frame[0])?, Turn::try_from(frame[1])?);
+        events.push(RobotEvent::Paint { colour });
+        events.push(RobotEvent::Turn { dir });
+        Ok(())
+    }
+}
+
 pub fn run() {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 11).unwrap();
@@ -89,11 +112,13 @@ fn get_paint_data(part1: bool, memory: Vec<i64>, verbose: bool) -> (i64, Panels,
     let (mut x, mut y) = (0, 0);
     let mut panels = HashMap::<Point, Colour>::new();
     let mut painted = HashSet::<Point>::new();
-    let mut state = State::Paint;
     let mut dir = Dir4::Up;
 
     let mut c = IntcodeComputer::new(memory);
-    c.run(verbose);
+    let mut robot = Peripheral::new(Robot);
+    if verbose {
+        c.set_trace(TextTrace(io::stdout()));
+    }
 
     let (mut min_x, mut min_y, mut max_x, mut max_y) = (
         OptionMinMax(None),
@@ -108,53 +133,37 @@ fn get_paint_data(part1: bool, memory: Vec<i64>, verbose: bool) -> (i64, Panels,
         min_y = min_y.min(y);
         max_y = max_y.max(y);
 
-        match c.get_state() {
-            intcode::State::BlockedOnInput => {
-                c.provide_input(
-                    (*panels
-                        .entry((x, y))
-                        .or_insert(if part1 || x != 0 || y != 0 {
-                            Colour::Black
-                        } else {
-                            Colour::White
-                        }))
-                    .into(),
-                    verbose,
-                );
-                c.run(verbose);
-            }
-            intcode::State::BlockedOnOutput => {
-                let o = c.get_output(verbose);
-                match state {
-                    State::Paint => {
-                        panels
-                            .entry((x, y))
-                            .and_modify(|e| *e = o.into())
-                            .or_insert(o.into());
-                        if !painted.contains(&(x, y)) {
-                            painted_count += 1;
-                        }
-                        painted.insert((x, y));
-                        state = State::Move;
-                    }
-                    State::Move => {
-                        match Turn::from(o) {
-                            Turn::Left => {
-                                dir = dir.rotate_left();
-                            }
-                            Turn::Right => {
-                                dir = dir.rotate_right();
-                            }
-                        }
-                        let d = dir.delta();
-                        (x, y) = (x + d.0 as i64, y + d.1 as i64);
-                        state = State::Paint;
+        if c.get_state() == State::Terminated {
+            break;
+        }
+
+        c.push_input(
+            (*panels
+                .entry((x, y))
+                .or_insert(if part1 || x != 0 || y != 0 {
+                    Colour::Black
+                } else {
+                    Colour::White
+                }))
+            .into(),
+        );
+        for event in robot.poll(&mut c).unwrap() {
+            match event {
+                RobotEvent::Paint { colour } => {
+                    panels.insert((x, y), colour);
+                    if painted.insert((x, y)) {
+                        painted_count += 1;
                     }
                 }
-                c.run(verbose);
+                RobotEvent::Turn { dir: turn } => {
+                    dir = match turn {
+                        Turn::Left => dir.rotate_left(),
+                        Turn::Right => dir.rotate_right(),
+                    };
+                    let d = dir.delta();
+                    (x, y) = (x + d.0 as i64, y + d.1 as i64);
+                }
             }
-            intcode::State::Terminated => break,
-            _ => panic!("invalid state"),
         }
     }
 
@@ -1,9 +1,13 @@
-use crate::common::intcode::{IntcodeComputer, State};
+use crate::common::intcode::{
+    IntcodeComputer, State,
+    peripheral::{Device, Peripheral},
+    trace::TextTrace,
+};
 
 use std::{
     cmp::Ordering,
     collections::HashMap,
-    io::{BufWriter, Write},
+    io::{self, BufWriter, Write},
 };
 
 use aoclib_rs::{option_min_max::OptionMinMax, prep_io, printwriteln, split_and_parse};
@@ -17,15 +21,17 @@ enum Tile {
     Ball,
 }
 
-impl From<i64> for Tile {
-    fn from(i: i64) -> Tile {
+impl TryFrom<i64> for Tile {
+    type Error = String;
+
+    fn try_from(i: i64) -> Result<Tile, String> {
         match i {
-            0 => Tile::Empty,
-            1 => Tile::Wall,
-            2 => Tile::Block,
-            3 => Tile::HorizontalPaddle,
-            4 => Tile::Ball,
-            _ => panic!("invalid tile"),
+            0 => Ok(Tile::Empty),
+            1 => Ok(Tile::Wall),
+            2 => Ok(Tile::Block),
+            3 => Ok(Tile::HorizontalPaddle),
+            4 => Ok(Tile::Ball),
+            _ => Err(format!("invalid tile {}", i)),
         }
     }
 }
@@ -59,6 +65,33 @@ impl From<Joystick> for i64 {
     }
 }
 
+enum ArcadeEvent {
+    Draw { x: i64, y: i64, tile: Tile },
+    Score(i64),
+}
+
+/// The arcade cabinet's screen: `(x, y, tile)` triples, or `(-1, 0, score)`.
+struct Arcade;
+
+impl Device for Arcade {
+    type Event = ArcadeEvent;
+
+    const FRAME_LEN: usize = 3;
+
+    fn decode(&mut self, frame: &[i64], events: &mut Vec<ArcadeEvent>) -> Result<(), String> {
+        events.push(match *frame {
+            [-1, 0, score] => ArcadeEvent::Score(score),
+            [x, y, tile] if x >= 0 && y >= 0 => ArcadeEvent::Draw {
+                x,
+                y,
+                tile: Tile::try_from(tile)?,
+            },
+            _ => return Err("position off the screen".to_string()),
+        });
+        Ok(())
+    }
+}
+
 pub fn run() {
     let mut contents = String::new();
     let (mut writer, contents) = prep_io(&mut contents, 13).unwrap();
@@ -69,12 +102,14 @@ pub fn run() {
 }
 
 fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
-    const VERBOSE: bool = true;
-
     let mut screen: HashMap<(i64, i64), Tile> = HashMap::new();
 
     let mut c = IntcodeComputer::new(memory);
-    c.run(VERBOSE);
+    c.set_trace(TextTrace(io::stdout()));
+    let events = Peripheral::new(Arcade).poll(&mut c).unwrap();
+    if c.get_state() != State::Terminated {
+        panic!("invalid state");
+    }
 
     let (mut min_x, mut min_y, mut max_x, mut max_y) = (
         OptionMinMax(None),
@@ -82,32 +117,20 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
         OptionMinMax(None),
         OptionMinMax(None),
     );
-    loop {
-        match c.get_state() {
-            State::BlockedOnOutput => {
-                let x = c.get_output(VERBOSE);
-                c.run(VERBOSE);
-
-                min_x = min_x.min(x);
-                max_x = max_x.max(x);
-
-                let y = c.get_output(VERBOSE);
-                c.run(VERBOSE);
-
-                min_y = min_y.min(y);
-                max_y = max_y.max(y);
-
-                let tile = Tile::from(c.get_output(VERBOSE));
-                screen
-                    .entry((x, y))
-                    .and_modify(|e| *e = tile)
-                    .or_insert(tile);
-
-                c.run(VERBOSE);
-            }
-            State::Terminated => break,
-            _ => panic!("invalid state"),
-        }
+    for event in events {
+        let ArcadeEvent::Draw { x, y, tile } = event else {
+            continue;
+        };
+
+        min_x = min_x.min(x);
+        max_x = max_x.max(x);
+        min_y = min_y.min(y);
+        max_y = max_y.max(y);
+
+        screen
+            .entry((x, y))
+            .and_modify(|e| *e = tile)
+            .or_insert(tile);
     }
 
     // min_x: 0, min_y: 0, max_x: 39, max_y: 24
@@ -126,7 +149,6 @@ fn part1<W: Write>(writer: &mut BufWriter<W>, memory: Vec<i64>) {
 }
 
 fn part2<W: Write>(writer: &mut BufWriter<W>, mut memory: Vec<i64>) {
-    const VERBOSE: bool = false;
     const HEIGHT: usize = 25;
     const WIDTH: usize = 40;
     const PRINT_EVERY: u32 = 50;
@@ -135,13 +157,27 @@ fn part2<W: Write>(writer: &mut BufWriter<W>, mut memory: Vec<i64>) {
 
     memory[0] = 2;
     let mut c = IntcodeComputer::new(memory);
-    c.run(VERBOSE);
+    let mut arcade = Peripheral::new(Arcade);
 
     let mut score = 0;
     let mut ticks = 0;
     let mut ball_x: Option<i64> = None;
     let mut paddle_x: Option<i64> = None;
     loop {
+        for event in arcade.poll(&mut c).unwrap() {
+            match event {
+                ArcadeEvent::Score(s) => score = s,
+                ArcadeEvent::Draw { x, y, tile } => {
+                    screen[y as usize][x as usize] = tile;
+                    match tile {
+                        Tile::HorizontalPaddle => paddle_x = Some(x),
+                        Tile::Ball => ball_x = Some(x),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
         match c.get_state() {
             State::BlockedOnInput => {
                 ticks += 1;
@@ -156,37 +192,13 @@ fn part2<W: Write>(writer: &mut BufWriter<W>, mut memory: Vec<i64>) {
                     ticks = 0;
                 }
 
-                c.provide_input(
-                    i64::from(match paddle_x.expect("").cmp(&ball_x.expect("")) {
+                c.push_input(i64::from(
+                    match paddle_x.expect("").cmp(&ball_x.expect("")) {
                         Ordering::Less => Joystick::Right,
                         Ordering::Equal => Joystick::Neutral,
                         Ordering::Greater => Joystick::Left,
-                    }),
-                    VERBOSE,
-                );
-
-                c.run(VERBOSE);
-            }
-            State::BlockedOnOutput => {
-                let x = c.get_output(VERBOSE);
-                c.run(VERBOSE);
-
-                let y = c.get_output(VERBOSE);
-                c.run(VERBOSE);
-
-                if x == -1 && y == 0 {
-                    score = c.get_output(VERBOSE);
-                } else {
-                    let tile = Tile::from(c.get_output(VERBOSE));
-                    screen[y as usize][x as usize] = tile;
-                    match tile {
-                        Tile::HorizontalPaddle => paddle_x = Some(x),
-                        Tile::Ball => ball_x = Some(x),
-                        _ => {}
-                    }
-                }
-
-                c.run(VERBOSE);
+                    },
+                ));
             }
             State::Terminated => break,
             _ => panic!("invalid state"),
@@ -0,0 +1,417 @@
+use aoc19::common::intcode::{
+    IntcodeComputer, State, asm,
+    budget::Budget,
+    cfg::Cfg,
+    debugger::Debugger,
+    decompile::decompile,
+    disasm,
+    memory::Memory,
+    ports::{InputPolicy, IterInput, OutputSink},
+    replay::{self, Recording},
+    trace::{TextTrace, json_escape},
+    transpile::transpile,
+};
+
+use std::{
+    cell::RefCell,
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::PathBuf,
+    process,
+    rc::Rc,
+};
+
+use aoclib_rs::split_and_parse;
+use clap::{Args, Subcommand, ValueEnum};
+
+/// Exit code for a program that stopped with an error, including running out of input.
+const EXIT_ERROR: i32 = 1;
+/// Exit code for bad arguments or input, matching clap's own usage errors.
+const EXIT_USAGE: i32 = 2;
+/// Exit code for a program stopped by `--max-steps`.
+const EXIT_STEP_LIMIT: i32 = 3;
+
+#[derive(Subcommand, Debug)]
+pub enum IntcodeCommand {
+    /// Assemble an Intcode assembly file into a comma-separated program.
+    Asm {
+        /// Assembly source file.
+        file: PathBuf,
+    },
+
+    /// Print the control-flow graph of an Intcode program as Graphviz DOT.
+    Cfg {
+        /// Comma-separated Intcode program.
+        file: PathBuf,
+    },
+
+    /// Step through an Intcode program interactively.
+    Debug {
+        /// Comma-separated Intcode program.
+        file: PathBuf,
+
+        /// Read debugger commands from this file instead of stdin.
+        #[arg(long)]
+        script: Option<PathBuf>,
+    },
+
+    /// Print an Intcode program as structured pseudo-code.
+    Decompile {
+        /// Comma-separated Intcode program.
+        file: PathBuf,
+    },
+
+    /// Print an annotated disassembly of an Intcode program.
+    Disasm {
+        /// Comma-separated Intcode program.
+        file: PathBuf,
+    },
+
+    /// Run an Intcode program.
+    ///
+    /// Exits with 0 if the program halts, 1 if it fails (including running out of input), 2 for
+    /// bad arguments and 3 if it's stopped by --max-steps.
+    Run(RunArgs),
+
+    /// Check that an Intcode program does exactly what a recording from `run --record` says,
+    /// feeding it the recorded input.
+    ///
+    /// Exits with 0 if it matches and 1 at the first divergence.
+    Replay {
+        /// Comma-separated Intcode program.
+        file: PathBuf,
+
+        /// Recording made with `run --record`.
+        recording: PathBuf,
+
+        /// Set memory before running, as for `run`.
+        #[arg(long, value_name = "ADDR=VALUE", value_parser = parse_poke)]
+        poke: Vec<(usize, i64)>,
+    },
+
+    /// Translate an Intcode program into a Rust module.
+    Transpile {
+        /// Comma-separated Intcode program.
+        file: PathBuf,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Comma-separated Intcode program.
+    file: PathBuf,
+
+    /// Input values, comma-separated (or text, with --input-format ascii). Without this or
+    /// --input-file, input is read from stdin as the program asks for it.
+    #[arg(long, allow_hyphen_values = true, conflicts_with = "input_file")]
+    input: Option<String>,
+
+    /// Read input from this file instead.
+    #[arg(long)]
+    input_file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Format::Numeric)]
+    input_format: Format,
+
+    #[arg(long, value_enum, default_value_t = Format::Numeric)]
+    output_format: Format,
+
+    /// Set memory before running, e.g. `--poke 0=2`. May be repeated.
+    #[arg(long, value_name = "ADDR=VALUE", value_parser = parse_poke)]
+    poke: Vec<(usize, i64)>,
+
+    /// Print each executed instruction to stderr.
+    #[arg(long)]
+    trace: bool,
+
+    /// Stop after this many instructions.
+    #[arg(long)]
+    max_steps: Option<u64>,
+
+    /// Save every input and output, with step numbers, to this file for `replay`.
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+enum Format {
+    /// Integers separated by commas or whitespace; output one per line.
+    Numeric,
+    /// Text as character codes; output values outside the ASCII range are printed as numbers on
+    /// their own line.
+    Ascii,
+    /// Output only: a single JSON object with the outputs and how the program stopped.
+    Json,
+}
+
+pub fn run(cmd: IntcodeCommand) {
+    let code = command(cmd).unwrap_or_else(|e| usage(&e));
+    if code != 0 {
+        process::exit(code);
+    }
+}
+
+/// Runs `cmd` and returns the exit code, or a usage error.
+fn command(cmd: IntcodeCommand) -> Result<i32, String> {
+    let mut stdout = io::stdout().lock();
+    let written = match cmd {
+        IntcodeCommand::Asm { file } => {
+            let source =
+                fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            match asm::assemble(&source) {
+                Ok(program) => writeln!(
+                    stdout,
+                    "{}",
+                    program
+                        .iter()
+                        .map(|i| i.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+                Err(e) => {
+                    eprintln!("{}:{}", file.display(), e);
+                    return Ok(EXIT_ERROR);
+                }
+            }
+        }
+        IntcodeCommand::Cfg { file } => Cfg::build(&read_program(&file)?).write_dot(stdout),
+        IntcodeCommand::Debug { file, script } => {
+            let mut debugger = Debugger::new(read_program(&file)?);
+            match script {
+                Some(script) => {
+                    let script =
+                        File::open(&script).map_err(|e| format!("{}: {}", script.display(), e))?;
+                    debugger.repl(BufReader::new(script), &mut stdout, false)
+                }
+                None => debugger.repl(io::stdin().lock(), &mut stdout, true),
+            }
+        }
+        IntcodeCommand::Decompile { file } => {
+            write!(stdout, "{}", decompile(&read_program(&file)?))
+        }
+        IntcodeCommand::Disasm { file } => disasm::disassemble(&Memory::from(read_program(&file)?))
+            .iter()
+            .try_for_each(|line| writeln!(stdout, "{}", line)),
+        IntcodeCommand::Run(args) => {
+            drop(stdout);
+            return Ok(run_program(args));
+        }
+        IntcodeCommand::Replay {
+            file,
+            recording,
+            poke,
+        } => {
+            let recording = Recording::load(&recording)
+                .map_err(|e| format!("{}: {}", recording.display(), e))?;
+            let mut c = IntcodeComputer::new(read_program(&file)?);
+            for (addr, value) in &poke {
+                c.write_mem(*addr, *value);
+            }
+            match replay::verify(&mut c, &recording) {
+                Ok(()) => writeln!(stdout, "ok: {} events matched", recording.events.len()),
+                Err(d) => {
+                    eprintln!("{}", d);
+                    return Ok(EXIT_ERROR);
+                }
+            }
+        }
+        IntcodeCommand::Transpile { file } => {
+            write!(stdout, "{}", transpile(&read_program(&file)?))
+        }
+    };
+    Ok(written.map_or_else(|e| write_failed(&e), |()| 0))
+}
+
+/// Runs a program as set up by `args` and returns the exit code.
+fn run_program(args: RunArgs) -> i32 {
+    if args.input_format == Format::Json {
+        return usage("--input-format json isn't supported");
+    }
+
+    let stdin_error = Rc::new(RefCell::new(None));
+    let input: Box<dyn Iterator<Item = i64>> = match (&args.input, &args.input_file) {
+        (Some(text), _) => match parse_input(text, args.input_format) {
+            Ok(values) => Box::new(values.into_iter()),
+            Err(e) => return usage(&e),
+        },
+        (None, Some(file)) => {
+            let text = match fs::read_to_string(file) {
+                Ok(text) => text,
+                Err(e) => return usage(&format!("{}: {}", file.display(), e)),
+            };
+            match parse_input(&text, args.input_format) {
+                Ok(values) => Box::new(values.into_iter()),
+                Err(e) => return usage(&format!("{}: {}", file.display(), e)),
+            }
+        }
+        (None, None) => stdin_input(args.input_format, Rc::clone(&stdin_error)),
+    };
+
+    let mut outputs = Vec::new();
+    let format = args.output_format;
+    let program = match read_program(&args.file) {
+        Ok(program) => program,
+        Err(e) => return usage(&e),
+    };
+    let mut c = IntcodeComputer::new(program)
+        .with_input(IterInput(input))
+        .with_input_policy(InputPolicy::Fail)
+        .with_output(Printer {
+            out: io::stdout().lock(),
+            format,
+            outputs: &mut outputs,
+        })
+        .with_budget(Budget {
+            max_steps: args.max_steps,
+            ..Budget::default()
+        });
+    for (addr, value) in &args.poke {
+        c.write_mem(*addr, *value);
+    }
+    if args.trace {
+        c.set_trace(TextTrace(io::stderr()));
+    }
+    if args.record.is_some() {
+        c = c.with_recording();
+    }
+
+    let result = c.run();
+    let (state, steps) = (c.get_state(), c.steps());
+    let recording = c.take_recording();
+    drop(c);
+
+    if let (Some(path), Some(recording)) = (&args.record, recording)
+        && let Err(e) = recording.save(path)
+    {
+        eprintln!("error: {}: {}", path.display(), e);
+        return EXIT_ERROR;
+    }
+    if let Some(e) = stdin_error.take() {
+        return usage(&e);
+    }
+
+    let code = match (&result, state) {
+        (Err(_), _) => EXIT_ERROR,
+        (Ok(()), State::Terminated) => 0,
+        (Ok(()), State::BudgetExhausted) => EXIT_STEP_LIMIT,
+        (Ok(()), _) => EXIT_ERROR,
+    };
+    if format == Format::Json {
+        let outputs: Vec<_> = outputs.iter().map(|v| v.to_string()).collect();
+        let error = match &result {
+            Err(e) => format!(",\"error\":\"{}\"", json_escape(&e.to_string())),
+            Ok(()) => String::new(),
+        };
+        if let Err(e) = writeln!(
+            io::stdout(),
+            "{{\"outputs\":[{}],\"state\":\"{:?}\",\"steps\":{}{}}}",
+            outputs.join(","),
+            state,
+            steps,
+            error
+        ) {
+            return write_failed(&e);
+        }
+    }
+    match result {
+        Err(e) => eprintln!("error: {}", e),
+        Ok(()) if code == EXIT_STEP_LIMIT => eprintln!("stopped after {} steps", steps),
+        Ok(()) if code != 0 => eprintln!("stopped in state {:?}", state),
+        Ok(()) => {}
+    }
+    code
+}
+
+/// Prints a running program's output in `format`, or keeps it in `outputs` for JSON.
+struct Printer<'a, W: Write> {
+    out: W,
+    format: Format,
+    outputs: &'a mut Vec<i64>,
+}
+
+impl<W: Write> OutputSink for Printer<'_, W> {
+    fn output(&mut self, value: i64) -> io::Result<()> {
+        match self.format {
+            Format::Numeric => writeln!(self.out, "{}", value),
+            Format::Ascii => match u8::try_from(value) {
+                Ok(b) if b.is_ascii() => self.out.write_all(&[b]),
+                _ => writeln!(self.out, "{}", value),
+            },
+            Format::Json => {
+                self.outputs.push(value);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_input(text: &str, format: Format) -> Result<Vec<i64>, String> {
+    match format {
+        Format::Ascii => Ok(text.bytes().map(i64::from).collect()),
+        _ => text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.parse().map_err(|_| format!("bad input value '{}'", t)))
+            .collect(),
+    }
+}
+
+/// Reads input from stdin only as the program asks for it, so interactive programs work. Input
+/// ends at a value that doesn't parse, leaving the message in `error`.
+fn stdin_input(
+    format: Format,
+    error: Rc<RefCell<Option<String>>>,
+) -> Box<dyn Iterator<Item = i64>> {
+    match format {
+        Format::Ascii => Box::new(
+            BufReader::new(io::stdin())
+                .bytes()
+                .map_while(Result::ok)
+                .map(i64::from),
+        ),
+        _ => Box::new(
+            io::stdin()
+                .lines()
+                .map_while(Result::ok)
+                .map_while(move |line| match parse_input(&line, format) {
+                    Ok(values) => Some(values),
+                    Err(e) => {
+                        error.replace(Some(e));
+                        None
+                    }
+                })
+                .flatten(),
+        ),
+    }
+}
+
+fn parse_poke(s: &str) -> Result<(usize, i64), String> {
+    let (addr, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDR=VALUE, got '{}'", s))?;
+    let addr = addr
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad address '{}'", addr))?;
+    let value = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad value '{}'", value))?;
+    Ok((addr, value))
+}
+
+/// Reports a failed write to stdout, e.g. into a pipe that's been closed.
+fn write_failed(e: &io::Error) -> i32 {
+    eprintln!("error: failed to write output: {}", e);
+    EXIT_ERROR
+}
+
+fn usage(msg: &str) -> i32 {
+    eprintln!("error: {}", msg);
+    EXIT_USAGE
+}
+
+fn read_program(file: &PathBuf) -> Result<Vec<i64>, String> {
+    let contents = fs::read_to_string(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    split_and_parse(contents.trim(), ",").map_err(|e| format!("{}: {}", file.display(), e))
+}
@@ -1,16 +1,32 @@
-mod common;
-mod days;
+mod intcode_cli;
 
-use clap::Parser;
+use aoc19::days;
+
+use clap::{Parser, Subcommand};
 
 /// Advent of Code 2019
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 struct Cli {
     /// The day to run.
-    day: u8,
+    day: Option<u8>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Intcode tools.
+    #[command(subcommand)]
+    Intcode(intcode_cli::IntcodeCommand),
 }
 
 fn main() {
-    days::run(Cli::parse().day);
+    let cli = Cli::parse();
+    match (cli.day, cli.command) {
+        (Some(day), _) => days::run(day),
+        (None, Some(Command::Intcode(cmd))) => intcode_cli::run(cmd),
+        (None, None) => unreachable!("clap requires a day or command"),
+    }
 }